@@ -0,0 +1,394 @@
+//! Procedural macros for [`ic-llm`](https://docs.rs/ic-llm).
+//!
+//! These macros are re-exported by `ic-llm` when its `macros` feature is enabled,
+//! and should be used through it.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Expr, FnArg, GenericArgument, ItemFn, Lit, LitStr, Pat,
+    PathArguments, Type,
+};
+
+/// Turns a function into a tool that can be offered to the LLM.
+///
+/// The function keeps working as before. Next to it, a module with the same name
+/// is generated, containing:
+///
+/// - `NAME`: the name of the tool, which defaults to the name of the function.
+/// - `tool()`: the `ic_llm::Tool` definition. Its description is taken from the
+///   doc comment of the function, and the description of each parameter from the
+///   doc comment of the corresponding argument. Arguments of type `Option<T>` are
+///   optional, all others are required.
+/// - `call(FunctionCall)`: parses the arguments of a tool call, calls the function
+///   and converts its result with `ic_llm::IntoToolResult`.
+///
+/// `bool` arguments are declared as `ParameterType::Boolean`, integer arguments as
+/// `ParameterType::Integer` and floating-point arguments as `ParameterType::Number`.
+/// They are parsed with `FunctionCall::get_as`, i.e. with `FromStr`, like arguments
+/// of any other type, which are declared as `ParameterType::String`. `Vec<T>`
+/// arguments, where `T` is one of these types, `String` or a `Vec` itself, are
+/// declared as arrays and deserialized from JSON with
+/// `FunctionCall::get_deserialized`. Other argument types, such as tuples or
+/// maps, are rejected.
+///
+/// See the documentation of `ic_llm::tool` for an example.
+///
+/// The name of the tool can be overridden with `#[ic_llm::tool(name = "...")]`.
+#[proc_macro_attribute]
+pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported tool attribute, expected `name`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let function = parse_macro_input!(item as ItemFn);
+    expand_tool(name, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A typed argument of a tool function.
+struct Argument {
+    ident: syn::Ident,
+    ty: Type,
+    description: Option<String>,
+    /// The `T` of an `Option<T>` argument.
+    optional: Option<Type>,
+}
+
+fn expand_tool(name: Option<LitStr>, mut function: ItemFn) -> syn::Result<TokenStream2> {
+    let fn_ident = function.sig.ident.clone();
+    let vis = function.vis.clone();
+    let tool_name = name.map_or_else(|| fn_ident.to_string(), |name| name.value());
+    let description = doc_comment(&function.attrs);
+
+    let mut arguments = Vec::new();
+    for input in function.sig.inputs.iter_mut() {
+        let FnArg::Typed(pat_type) = input else {
+            return Err(syn::Error::new_spanned(input, "tools cannot take `self`"));
+        };
+        let Pat::Ident(pat_ident) = pat_type.pat.as_ref() else {
+            return Err(syn::Error::new_spanned(
+                &pat_type.pat,
+                "tool arguments must be plain identifiers",
+            ));
+        };
+        if let Type::Reference(_) = pat_type.ty.as_ref() {
+            return Err(syn::Error::new_spanned(
+                &pat_type.ty,
+                "tool arguments must be owned types",
+            ));
+        }
+
+        // Doc comments are not allowed on function arguments, so they are removed
+        // once they have been turned into parameter descriptions.
+        let description = doc_comment(&pat_type.attrs);
+        pat_type.attrs.retain(|attr| !attr.path().is_ident("doc"));
+
+        arguments.push(Argument {
+            ident: pat_ident.ident.clone(),
+            ty: pat_type.ty.as_ref().clone(),
+            description,
+            optional: option_inner_type(&pat_type.ty).cloned(),
+        });
+    }
+
+    let tool_description = description.map(|d| quote!(.with_description(#d)));
+    let parameter_types = arguments
+        .iter()
+        .map(|arg| parameter_type(arg.optional.as_ref().unwrap_or(&arg.ty), &arg.ident))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let parameters = arguments
+        .iter()
+        .zip(&parameter_types)
+        .map(|(arg, parameter_type)| {
+            let name = arg.ident.to_string();
+            let description = arg
+                .description
+                .as_ref()
+                .map(|d| quote!(.with_description(#d)));
+            let required = arg.optional.is_none().then(|| quote!(.is_required()));
+            quote! {
+                .with_parameter(
+                    ::ic_llm::parameter(#name, #parameter_type)
+                        #description
+                        #required
+                )
+            }
+        });
+
+    let call_ident = format_ident!("__ic_llm_call");
+    let extractions = arguments.iter().map(|arg| {
+        let ident = &arg.ident;
+        let ty = &arg.ty;
+        let name = ident.to_string();
+        let value_ty = arg.optional.as_ref().unwrap_or(ty);
+        // Arrays are passed as JSON, everything else is parsed with `FromStr`.
+        let get = if generic_inner_type(value_ty, "Vec").is_some() {
+            quote!(get_deserialized::<#value_ty>)
+        } else {
+            quote!(get_as::<#value_ty>)
+        };
+        let value = match &arg.optional {
+            Some(_) => quote! {
+                match #call_ident.#get(#name) {
+                    ::core::result::Result::Ok(value) => ::core::option::Option::Some(value),
+                    ::core::result::Result::Err(::ic_llm::ArgumentError::Missing(_)) => {
+                        ::core::option::Option::None
+                    }
+                    ::core::result::Result::Err(e) => {
+                        return ::core::result::Result::Err(::ic_llm::ToolError::from(e));
+                    }
+                }
+            },
+            None => quote!(#call_ident.#get(#name)?),
+        };
+        quote!(let #ident: #ty = #value;)
+    });
+    // Reports arguments that can't be parsed with an error that names their type.
+    let parsed_types = arguments
+        .iter()
+        .map(|arg| arg.optional.as_ref().unwrap_or(&arg.ty))
+        .filter(|ty| generic_inner_type(ty, "Vec").is_none())
+        .map(|ty| {
+            quote_spanned! {ty.span()=>
+                __ic_llm_assert_parsed::<#ty>();
+            }
+        });
+    let argument_idents = arguments.iter().map(|arg| &arg.ident);
+    let await_call = function.sig.asyncness.map(|_| quote!(.await));
+    let module_doc = format!("The `{tool_name}` tool, generated from [`{fn_ident}`].");
+
+    Ok(quote! {
+        #function
+
+        #[doc = #module_doc]
+        #vis mod #fn_ident {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// The name of the tool.
+            pub const NAME: &str = #tool_name;
+
+            /// Returns the definition of the tool.
+            pub fn tool() -> ::ic_llm::Tool {
+                ::ic_llm::tool(NAME)
+                    #tool_description
+                    #(#parameters)*
+                    .build()
+            }
+
+            /// Parses the arguments of a call to the tool and executes it.
+            pub async fn call(
+                #call_ident: ::ic_llm::FunctionCall,
+            ) -> ::core::result::Result<::std::string::String, ::ic_llm::ToolError> {
+                fn __ic_llm_assert_parsed<T: ::ic_llm::__private::ParsedArgument>() {}
+                #(#parsed_types)*
+                #(#extractions)*
+                ::ic_llm::IntoToolResult::into_tool_result(super::#fn_ident(#(#argument_idents),*)#await_call)
+            }
+        }
+    })
+}
+
+/// Joins the lines of the doc comments among `attrs`.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta.require_name_value().ok()?.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(s) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// Returns `T` if `ty` is `Option<T>`.
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    generic_inner_type(ty, "Option")
+}
+
+/// Returns `T` if `ty` is the generic type `name<T>`, e.g. `Vec<T>`.
+fn generic_inner_type<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
+
+/// Returns the name of the last segment of a path type, e.g. `u64` or `String`.
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the `ParameterType` variant of a scalar type, if it is one.
+fn scalar_type(name: &str) -> Option<&'static str> {
+    match name {
+        "bool" => Some("Boolean"),
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => Some("Integer"),
+        "f32" | "f64" => Some("Number"),
+        _ => None,
+    }
+}
+
+/// Returns the `ParameterType` used to declare the argument `ident` of type `ty`.
+fn parameter_type(ty: &Type, ident: &syn::Ident) -> syn::Result<TokenStream2> {
+    if let Some(items) = generic_inner_type(ty, "Vec") {
+        let items = element_type(items, ident)?;
+        return Ok(quote!(::ic_llm::ParameterType::array_of(#items)));
+    }
+    match type_name(ty).as_deref() {
+        None | Some("HashMap" | "BTreeMap" | "HashSet" | "BTreeSet" | "VecDeque") => {
+            Err(syn::Error::new_spanned(
+                ty,
+                format!(
+                    "unsupported type of tool argument `{ident}`, \
+                     expected a type that implements `FromStr` or a `Vec`"
+                ),
+            ))
+        }
+        Some(name) => {
+            let variant = format_ident!("{}", scalar_type(name).unwrap_or("String"));
+            Ok(quote!(::ic_llm::ParameterType::#variant))
+        }
+    }
+}
+
+/// Returns the `ParameterType` of the elements of a `Vec` argument `ident`.
+fn element_type(ty: &Type, ident: &syn::Ident) -> syn::Result<TokenStream2> {
+    if let Some(items) = generic_inner_type(ty, "Vec") {
+        let items = element_type(items, ident)?;
+        return Ok(quote!(::ic_llm::ParameterType::array_of(#items)));
+    }
+    let variant = match type_name(ty).as_deref() {
+        Some("String") => Some("String"),
+        Some(name) => scalar_type(name),
+        None => None,
+    };
+    let Some(variant) = variant else {
+        return Err(syn::Error::new_spanned(
+            ty,
+            format!(
+                "unsupported element type of tool argument `{ident}`, \
+                 expected a boolean, a number, a `String` or a `Vec`"
+            ),
+        ));
+    };
+    let variant = format_ident!("{}", variant);
+    Ok(quote!(::ic_llm::ParameterType::#variant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn doc_comment_joins_lines() {
+        let function: ItemFn = parse_quote! {
+            /// Lookup the balance
+            /// of an ICP account.
+            fn f() {}
+        };
+        assert_eq!(
+            doc_comment(&function.attrs),
+            Some("Lookup the balance\nof an ICP account.".to_string())
+        );
+        assert_eq!(doc_comment(&[]), None);
+    }
+
+    #[test]
+    fn option_inner_type_detects_options() {
+        let ty: Type = parse_quote!(Option<u64>);
+        assert_eq!(option_inner_type(&ty), Some(&parse_quote!(u64)));
+        let ty: Type = parse_quote!(std::option::Option<String>);
+        assert_eq!(option_inner_type(&ty), Some(&parse_quote!(String)));
+        let ty: Type = parse_quote!(Vec<u64>);
+        assert_eq!(option_inner_type(&ty), None);
+    }
+
+    #[test]
+    fn parameter_types() {
+        let ident = format_ident!("arg");
+        let declared = |ty: Type| parameter_type(&ty, &ident).map(|t| t.to_string());
+        assert_eq!(
+            declared(parse_quote!(bool)).unwrap(),
+            quote!(::ic_llm::ParameterType::Boolean).to_string()
+        );
+        assert_eq!(
+            declared(parse_quote!(u64)).unwrap(),
+            quote!(::ic_llm::ParameterType::Integer).to_string()
+        );
+        assert_eq!(
+            declared(parse_quote!(f32)).unwrap(),
+            quote!(::ic_llm::ParameterType::Number).to_string()
+        );
+        assert_eq!(
+            declared(parse_quote!(String)).unwrap(),
+            quote!(::ic_llm::ParameterType::String).to_string()
+        );
+        assert_eq!(
+            declared(parse_quote!(candid::Principal)).unwrap(),
+            quote!(::ic_llm::ParameterType::String).to_string()
+        );
+        assert_eq!(
+            declared(parse_quote!(Vec<Vec<u32>>)).unwrap(),
+            quote!(::ic_llm::ParameterType::array_of(
+                ::ic_llm::ParameterType::array_of(::ic_llm::ParameterType::Integer)
+            ))
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_types() {
+        let ident = format_ident!("accounts");
+        let error = |ty: Type| parameter_type(&ty, &ident).unwrap_err().to_string();
+        assert_eq!(
+            error(parse_quote!((String, u32))),
+            "unsupported type of tool argument `accounts`, \
+             expected a type that implements `FromStr` or a `Vec`"
+        );
+        assert_eq!(
+            error(parse_quote!(HashMap<String, u32>)),
+            "unsupported type of tool argument `accounts`, \
+             expected a type that implements `FromStr` or a `Vec`"
+        );
+        assert_eq!(
+            error(parse_quote!(Vec<Account>)),
+            "unsupported element type of tool argument `accounts`, \
+             expected a boolean, a number, a `String` or a `Vec`"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use ic_llm::testing::{block_on, tool_call};
+use ic_llm::{ArgumentError, Function, ParameterType, Tool, ToolError, ToolRegistry};
+
+/// Lookup the balance of an ICP account.
+#[ic_llm::tool]
+async fn lookup_icp_balance(
+    /// The ICP account (64-character hex string) to look up.
+    account: String,
+    /// The number of decimals to show.
+    decimals: Option<u32>,
+) -> String {
+    format!(
+        "Balance of {account} is {:.*} ICP",
+        decimals.unwrap_or(2) as usize,
+        1.5
+    )
+}
+
+#[ic_llm::tool(name = "convert")]
+fn convert_units(value: f64, to_feet: bool) -> Result<String, String> {
+    if value < 0.0 {
+        return Err("value must not be negative".to_string());
+    }
+    Ok(if to_feet {
+        format!("{:.1} ft", value * 3.28)
+    } else {
+        format!("{value} m")
+    })
+}
+
+/// Sum the amounts sent to each account.
+#[ic_llm::tool]
+fn total_amounts(accounts: Vec<String>, amounts: Option<Vec<Vec<u64>>>) -> String {
+    let totals: Vec<String> = accounts
+        .iter()
+        .zip(amounts.unwrap_or_default())
+        .map(|(account, amounts)| format!("{account}: {}", amounts.iter().sum::<u64>()))
+        .collect();
+    totals.join(", ")
+}
+
+#[test]
+fn generates_tool_definition() {
+    let expected = ic_llm::tool("lookup_icp_balance")
+        .with_description("Lookup the balance of an ICP account.")
+        .with_parameter(
+            ic_llm::parameter("account", ParameterType::String)
+                .with_description("The ICP account (64-character hex string) to look up.")
+                .is_required(),
+        )
+        .with_parameter(
+            ic_llm::parameter("decimals", ParameterType::Integer)
+                .with_description("The number of decimals to show."),
+        )
+        .build();
+
+    assert_eq!(lookup_icp_balance::NAME, "lookup_icp_balance");
+    assert_eq!(lookup_icp_balance::tool(), expected);
+}
+
+#[test]
+fn uses_custom_name_and_parameter_types() {
+    let Tool::Function(Function {
+        name,
+        description,
+        parameters,
+    }) = convert_units::tool();
+    let parameters = parameters.unwrap();
+
+    assert_eq!(name, "convert");
+    assert_eq!(description, None);
+    assert_eq!(parameters.properties.unwrap()[1].type_, "boolean");
+    assert_eq!(
+        parameters.required,
+        Some(vec!["value".to_string(), "to_feet".to_string()])
+    );
+}
+
+#[test]
+fn dispatches_typed_arguments() {
+    assert_eq!(
+        block_on(lookup_icp_balance::call(
+            tool_call(
+                "call_1",
+                "lookup_icp_balance",
+                &[("account", "abc"), ("decimals", "1")]
+            )
+            .function
+        )),
+        Ok("Balance of abc is 1.5 ICP".to_string())
+    );
+    assert_eq!(
+        block_on(convert_units::call(
+            tool_call("call_2", "convert", &[("value", "2"), ("to_feet", "true")]).function
+        )),
+        Ok("6.6 ft".to_string())
+    );
+    // The original functions can still be called directly.
+    assert_eq!(convert_units(1.0, false), Ok("1 m".to_string()));
+}
+
+#[test]
+fn declares_and_deserializes_arrays() {
+    let expected = ic_llm::tool("total_amounts")
+        .with_description("Sum the amounts sent to each account.")
+        .with_parameter(
+            ic_llm::parameter("accounts", ParameterType::array_of(ParameterType::String))
+                .is_required(),
+        )
+        .with_parameter(ic_llm::parameter(
+            "amounts",
+            ParameterType::array_of(ParameterType::array_of(ParameterType::Integer)),
+        ))
+        .build();
+    assert_eq!(total_amounts::tool(), expected);
+
+    assert_eq!(
+        block_on(total_amounts::call(
+            tool_call(
+                "call_1",
+                "total_amounts",
+                &[("accounts", r#"["a", "b"]"#), ("amounts", "[[1, 2], [3]]")]
+            )
+            .function
+        )),
+        Ok("a: 3, b: 3".to_string())
+    );
+    assert_eq!(
+        block_on(total_amounts::call(
+            tool_call("call_2", "total_amounts", &[("accounts", "a")]).function
+        )),
+        Err(ToolError::InvalidArguments(ArgumentError::Invalid {
+            name: "accounts".to_string(),
+            message: "expected value at line 1 column 1".to_string(),
+        }))
+    );
+}
+
+#[test]
+fn reports_argument_and_handler_errors() {
+    assert_eq!(
+        block_on(lookup_icp_balance::call(
+            tool_call("call_1", "lookup_icp_balance", &[]).function
+        )),
+        Err(ToolError::InvalidArguments(ArgumentError::Missing(
+            "account".to_string()
+        )))
+    );
+    assert_eq!(
+        block_on(convert_units::call(
+            tool_call(
+                "call_2",
+                "convert",
+                &[("value", "two"), ("to_feet", "true")]
+            )
+            .function
+        )),
+        Err(ToolError::InvalidArguments(ArgumentError::Invalid {
+            name: "value".to_string(),
+            message: "invalid float literal: \"two\"".to_string(),
+        }))
+    );
+    assert_eq!(
+        block_on(convert_units::call(
+            tool_call("call_3", "convert", &[("value", "-1"), ("to_feet", "true")]).function
+        )),
+        Err(ToolError::failed("value must not be negative"))
+    );
+}
+
+#[test]
+fn registers_generated_tools() {
+    let registry =
+        ToolRegistry::new().with_tool(lookup_icp_balance::tool(), lookup_icp_balance::call);
+
+    assert_eq!(
+        block_on(registry.dispatch(&tool_call(
+            "call_1",
+            "lookup_icp_balance",
+            &[("account", "abc")]
+        ))),
+        Ok("Balance of abc is 1.50 ICP".to_string())
+    );
+}
@@ -0,0 +1,284 @@
+use crate::chat::{AssistantMessage, ChatBuilder, ChatMessage, FunctionCall};
+use crate::error::LlmError;
+use crate::registry::{ToolError, ToolRegistry};
+use crate::tool::{Tool, ToolChoice};
+use std::future::Future;
+
+/// The default maximum number of tool rounds of an [`Agent`].
+const DEFAULT_MAX_ROUNDS: usize = 5;
+
+/// The outcome of [`Agent::run`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentResponse {
+    /// The final message of the model.
+    pub message: AssistantMessage,
+    /// All messages of the conversation, including the tool calls, their
+    /// results and the final message.
+    pub transcript: Vec<ChatMessage>,
+    /// The number of tool rounds that were executed.
+    pub rounds: usize,
+}
+
+/// An agent that executes the tool calls of a model until it produces a final answer.
+///
+/// Every request is sent with a clone of the [`ChatBuilder`] the agent was created
+/// with, so the model, canister, transport and retry policy are configured there.
+///
+/// # Example
+///
+/// ```
+/// use ic_llm::{Agent, AgentResponse, ChatMessage, LlmError, Model, ParameterType};
+///
+/// # async fn agent_example() -> Result<AgentResponse, LlmError> {
+/// let agent = Agent::new(ic_llm::chat(Model::Llama3_1_8B)).with_tool(
+///     ic_llm::tool("get_weather")
+///         .with_description("Get current weather for a location")
+///         .with_parameter(ic_llm::parameter("location", ParameterType::String).is_required())
+///         .build(),
+///     |call| async move {
+///         let location = call.get("location").unwrap_or_default();
+///         Ok(format!("Weather in {location}: Sunny, 72°F"))
+///     },
+/// );
+///
+/// agent
+///     .run(vec![ChatMessage::User {
+///         content: "What's the weather in San Francisco?".to_string(),
+///     }])
+///     .await
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Agent {
+    chat: ChatBuilder,
+    tools: ToolRegistry,
+    max_rounds: usize,
+}
+
+impl Agent {
+    /// Creates an agent that sends its requests with the given chat builder.
+    pub fn new(chat: ChatBuilder) -> Self {
+        Self {
+            chat,
+            tools: ToolRegistry::new(),
+            max_rounds: DEFAULT_MAX_ROUNDS,
+        }
+    }
+
+    /// Adds a tool, together with the handler that executes calls to it.
+    pub fn with_tool<F, Fut>(mut self, tool: Tool, handler: F) -> Self
+    where
+        F: Fn(FunctionCall) -> Fut + 'static,
+        Fut: Future<Output = Result<String, ToolError>> + 'static,
+    {
+        self.tools = self.tools.with_tool(tool, handler);
+        self
+    }
+
+    /// Sets the tools of the agent, replacing any tools added before.
+    pub fn with_registry(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Sets the maximum number of tool rounds.
+    ///
+    /// Once the limit is reached, the model is asked for a final answer without
+    /// being offered any tools. Defaults to 5.
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Runs the conversation until the model answers without calling any tools.
+    ///
+    /// Calls to unknown tools and failed handlers are reported back to the model
+    /// as tool messages, so that it can recover.
+    pub async fn run(&self, messages: Vec<ChatMessage>) -> Result<AgentResponse, LlmError> {
+        let mut transcript = messages;
+        let mut rounds = 0;
+
+        loop {
+            let mut chat = self.chat.clone().with_messages(transcript.clone());
+            chat = if rounds < self.max_rounds {
+                chat.with_tools(self.tools.tools())
+            } else {
+                // A tool choice that requires calls can't be met without tools.
+                chat.with_tools(Vec::new())
+                    .with_tool_choice(ToolChoice::Auto)
+            };
+
+            let message = chat.try_send().await?.message;
+            transcript.push(ChatMessage::Assistant(message.clone()));
+
+            if message.tool_calls.is_empty() || rounds == self.max_rounds {
+                return Ok(AgentResponse {
+                    message,
+                    transcript,
+                    rounds,
+                });
+            }
+
+            for tool_call in &message.tool_calls {
+                transcript.push(self.tools.execute(tool_call).await);
+            }
+            rounds += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        block_on, text_response, tool_call, tool_calls_response, MockLlm, RequestMatcher,
+    };
+    use crate::{Model, ToolBuilder};
+
+    fn agent(mock: &MockLlm) -> Agent {
+        Agent::new(ChatBuilder::new(Model::Llama3_1_8B).with_transport(mock.clone())).with_tool(
+            ToolBuilder::new("get_weather").build(),
+            |call| async move {
+                match call.get("location") {
+                    Some(location) => Ok(format!("Sunny in {location}")),
+                    None => Err(ToolError::failed("location is required")),
+                }
+            },
+        )
+    }
+
+    fn user(content: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage::User {
+            content: content.to_string(),
+        }]
+    }
+
+    #[test]
+    fn answers_without_tools() {
+        let mock = MockLlm::new();
+        mock.reply(text_response("Hello!"));
+
+        let response = block_on(agent(&mock).run(user("Hi"))).unwrap();
+
+        assert_eq!(response.message.content, Some("Hello!".to_string()));
+        assert_eq!(response.rounds, 0);
+        assert_eq!(response.transcript.len(), 2);
+        mock.assert_request(0, RequestMatcher::new().with_tool("get_weather"));
+    }
+
+    #[test]
+    fn executes_tool_calls_until_final_answer() {
+        let mock = MockLlm::new();
+        mock.reply(tool_calls_response(vec![tool_call(
+            "call_1",
+            "get_weather",
+            &[("location", "Cairo")],
+        )]))
+        .reply(tool_calls_response(vec![tool_call(
+            "call_2",
+            "get_weather",
+            &[("location", "Paris")],
+        )]))
+        .reply(text_response("Sunny in both cities."));
+
+        let response = block_on(agent(&mock).run(user("Weather in Cairo and Paris?"))).unwrap();
+
+        assert_eq!(
+            response.message.content,
+            Some("Sunny in both cities.".to_string())
+        );
+        assert_eq!(response.rounds, 2);
+        assert_eq!(response.transcript.len(), 6);
+        assert_eq!(
+            response.transcript[2],
+            ChatMessage::Tool {
+                content: "Sunny in Cairo".to_string(),
+                tool_call_id: "call_1".to_string(),
+            }
+        );
+        mock.assert_request(2, RequestMatcher::new().with_tool_result("call_2"));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn reports_unknown_tools_and_handler_errors_to_the_model() {
+        let mock = MockLlm::new();
+        mock.reply(tool_calls_response(vec![
+            tool_call("call_1", "get_time", &[]),
+            tool_call("call_2", "get_weather", &[]),
+        ]))
+        .reply(text_response("Sorry, I couldn't find out."));
+
+        let response = block_on(agent(&mock).run(user("Time and weather?"))).unwrap();
+
+        assert_eq!(
+            &response.transcript[2..4],
+            &[
+                ChatMessage::Tool {
+                    content: "Error: unknown tool: get_time".to_string(),
+                    tool_call_id: "call_1".to_string(),
+                },
+                ChatMessage::Tool {
+                    content: "Error: location is required".to_string(),
+                    tool_call_id: "call_2".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_offering_tools_after_max_rounds() {
+        let mock = MockLlm::new();
+        mock.reply(tool_calls_response(vec![tool_call(
+            "call_1",
+            "get_weather",
+            &[("location", "Cairo")],
+        )]))
+        .reply(text_response("Sunny in Cairo."));
+
+        let response = block_on(
+            agent(&mock)
+                .with_max_rounds(1)
+                .run(user("Weather in Cairo?")),
+        )
+        .unwrap();
+
+        assert_eq!(response.rounds, 1);
+        assert_eq!(mock.requests()[1].tools, None);
+    }
+
+    #[test]
+    fn final_round_resets_the_tool_choice() {
+        let mock = MockLlm::new();
+        mock.reply(tool_calls_response(vec![tool_call(
+            "call_1",
+            "get_weather",
+            &[("location", "Cairo")],
+        )]))
+        .reply(text_response("Sunny in Cairo."));
+
+        let agent = Agent::new(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_transport(mock.clone())
+                .with_tool_choice(ToolChoice::Required),
+        )
+        .with_registry(agent(&mock).tools)
+        .with_max_rounds(1);
+        let response = block_on(agent.run(user("Weather in Cairo?"))).unwrap();
+
+        assert_eq!(response.message.content.as_deref(), Some("Sunny in Cairo."));
+        assert_eq!(mock.requests()[0].tool_choice, Some(ToolChoice::Required));
+        assert_eq!(mock.requests()[1].tool_choice, Some(ToolChoice::Auto));
+    }
+
+    #[test]
+    fn propagates_llm_errors() {
+        let mock = MockLlm::new();
+        mock.fail(LlmError::Timeout("deadline expired".to_string()));
+
+        assert_eq!(
+            block_on(agent(&mock).run(user("Hi"))),
+            Err(LlmError::Timeout("deadline expired".to_string()))
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use crate::chat::ToolCallArgument;
+use crate::tool::Property;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use std::fmt;
+use std::str::FromStr;
+
+/// An error in the arguments of a tool call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgumentError {
+    /// A required argument is missing.
+    Missing(String),
+    /// An argument has a value that cannot be converted to the expected type.
+    Invalid { name: String, message: String },
+    /// An argument that the tool doesn't declare.
+    Unexpected(String),
+    /// An error that isn't specific to a single argument.
+    Other(String),
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgumentError::Missing(name) => write!(f, "missing required argument `{name}`"),
+            ArgumentError::Invalid { name, message } => {
+                write!(f, "invalid argument `{name}`: {message}")
+            }
+            ArgumentError::Unexpected(name) => write!(f, "unexpected argument `{name}`"),
+            ArgumentError::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+impl de::Error for ArgumentError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ArgumentError::Other(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ArgumentError::Missing(field.to_string())
+    }
+
+    fn unknown_field(field: &str, _expected: &'static [&'static str]) -> Self {
+        ArgumentError::Unexpected(field.to_string())
+    }
+}
+
+/// Deserializes the arguments of a tool call as a map from names to values.
+pub(crate) struct ArgumentsDeserializer<'a> {
+    arguments: std::slice::Iter<'a, ToolCallArgument>,
+    properties: &'a [Property],
+    current: Option<&'a ToolCallArgument>,
+}
+
+impl<'a> ArgumentsDeserializer<'a> {
+    /// Creates a deserializer for `arguments`, whose values are self-describing
+    /// according to their declared type in `properties`, if any.
+    pub(crate) fn new(arguments: &'a [ToolCallArgument], properties: &'a [Property]) -> Self {
+        Self {
+            arguments: arguments.iter(),
+            properties,
+            current: None,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ArgumentsDeserializer<'de> {
+    type Error = ArgumentError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for ArgumentsDeserializer<'de> {
+    type Error = ArgumentError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.current = self.arguments.next();
+        match self.current {
+            Some(argument) => seed
+                .deserialize(argument.name.as_str().into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let argument = self
+            .current
+            .expect("next_value_seed called before next_key_seed");
+        let type_ = self
+            .properties
+            .iter()
+            .find(|property| property.name == argument.name)
+            .map(|property| property.type_.as_str());
+        seed.deserialize(ValueDeserializer {
+            value: &argument.value,
+            type_,
+        })
+        .map_err(|e| match e {
+            ArgumentError::Other(message) => ArgumentError::Invalid {
+                name: argument.name.clone(),
+                message,
+            },
+            e => e,
+        })
+    }
+}
+
+/// Deserializes the value of an argument as `T`, which reads arrays and objects as JSON.
+pub(crate) fn deserialize_value<T: DeserializeOwned>(
+    argument: &ToolCallArgument,
+) -> Result<T, ArgumentError> {
+    T::deserialize(ValueDeserializer {
+        value: &argument.value,
+        type_: None,
+    })
+    .map_err(|e| match e {
+        ArgumentError::Other(message) => ArgumentError::Invalid {
+            name: argument.name.clone(),
+            message,
+        },
+        e => e,
+    })
+}
+
+/// Parses the value of an argument, or else the value without surrounding whitespace,
+/// returning a description of the error if neither can be parsed.
+pub(crate) fn parse_value<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .or_else(|_| value.trim().parse())
+        .map_err(|e: T::Err| format!("{e}: {value:?}"))
+}
+
+/// Deserializes the string value of an argument as the type requested by the visitor.
+///
+/// Arrays and objects are expected to be encoded as JSON. Visitors that accept any
+/// type get the value as the declared type of the argument, or else as a string.
+struct ValueDeserializer<'a> {
+    value: &'a str,
+    type_: Option<&'a str>,
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.parsed()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = ArgumentError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.type_ {
+            Some("integer") => match self.value.trim().parse::<i64>() {
+                Ok(value) => visitor.visit_i64(value),
+                Err(_) => visitor.visit_u64(self.parsed()?),
+            },
+            Some("number") => visitor.visit_f64(self.parsed()?),
+            Some("boolean") => visitor.visit_bool(self.parsed()?),
+            Some("array" | "object") => self.json(|de| de.deserialize_any(visitor)),
+            _ => visitor.visit_borrowed_str(self.value),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.value.into_deserializer())
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.json(|de| de.deserialize_seq(visitor))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.json(|de| de.deserialize_tuple(len, visitor))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.json(|de| de.deserialize_tuple_struct(name, len, visitor))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.json(|de| de.deserialize_map(visitor))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.json(|de| de.deserialize_struct(name, fields, visitor))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+impl<'de> ValueDeserializer<'de> {
+    fn parsed<T: FromStr>(&self) -> Result<T, ArgumentError>
+    where
+        T::Err: fmt::Display,
+    {
+        parse_value(self.value).map_err(ArgumentError::Other)
+    }
+
+    /// Deserializes the value as JSON, which is how the model passes arrays and objects.
+    fn json<T>(
+        self,
+        deserialize: impl FnOnce(
+            &mut serde_json::Deserializer<serde_json::de::StrRead<'de>>,
+        ) -> Result<T, serde_json::Error>,
+    ) -> Result<T, ArgumentError> {
+        let mut de = serde_json::Deserializer::from_str(self.value);
+        deserialize(&mut de)
+            .and_then(|value| de.end().map(|()| value))
+            .map_err(|e| ArgumentError::Other(e.to_string()))
+    }
+}
@@ -1,6 +1,15 @@
-use crate::tool::Tool;
+use crate::arguments::{self, ArgumentError, ArgumentsDeserializer};
+use crate::context::{self, ContextBudget};
+use crate::error::LlmError;
+use crate::retry::{self, RetryPolicy};
+use crate::structured;
+use crate::tool::{Parameters, Tool, ToolChoice};
+use crate::transport::{self, CanisterTransport, LlmTransport, SharedTransport, WaitMode};
 use candid::{CandidType, Principal};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 /// A message in a chat.
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -18,17 +27,191 @@ pub enum ChatMessage {
     },
 }
 
-#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+/// The response to a chat request.
+///
+/// The current LLM canister only returns the message, so `usage`, `finish_reason`
+/// and `model` are `None` in its responses.
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
 pub struct Response {
     pub message: AssistantMessage,
+    /// The number of tokens used by the request, if reported by the LLM canister.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Why the model stopped generating, if reported by the LLM canister.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// The model that served the request, if reported by the LLM canister.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+impl Response {
+    /// Returns whether the response was cut off because it reached the maximum
+    /// number of tokens.
+    ///
+    /// Always `false` if the LLM canister doesn't report the finish reason.
+    pub fn is_truncated(&self) -> bool {
+        self.finish_reason == Some(FinishReason::Length)
+    }
+}
+
+/// The number of tokens used by a request.
+#[derive(CandidType, Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+/// Why the model stopped generating a response.
+///
+/// Encoded as text, so that reasons this crate doesn't know about decode as
+/// [`FinishReason::Other`] instead of failing.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FinishReason {
+    /// The model finished its answer or produced a stop sequence.
+    Stop,
+    /// The response reached the maximum number of tokens.
+    Length,
+    /// The model called tools.
+    ToolCalls,
+    /// The response was withheld by a content filter.
+    ContentFilter,
+    /// Any other reason.
+    Other(String),
+}
+
+impl FinishReason {
+    /// Returns the reason as it is encoded on the wire.
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Other(reason) => reason,
+        }
+    }
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for FinishReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for FinishReason {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FinishReason {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(FinishReason::from(
+            String::deserialize(deserializer)?.as_str(),
+        ))
+    }
+}
+
+impl CandidType for FinishReason {
+    fn _ty() -> candid::types::Type {
+        String::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        serializer.serialize_text(self.as_str())
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct AssistantMessage {
     pub content: Option<String>,
     pub tool_calls: Vec<ToolCall>,
 }
 
+impl AssistantMessage {
+    /// Returns the reasoning of the model, i.e. the `<think>` block that reasoning
+    /// models such as `Model::Qwen3_32B` emit before their answer.
+    pub fn reasoning(&self) -> Option<&str> {
+        split_reasoning(self.content.as_deref()?, false).map(|(reasoning, _)| reasoning)
+    }
+
+    /// Returns the content without the reasoning of the model. Content that doesn't
+    /// start with a `<think>` block is returned as is.
+    pub fn answer(&self) -> Option<&str> {
+        let content = self.content.as_deref()?;
+        Some(split_reasoning(content, false).map_or(content, |(_, answer)| answer))
+    }
+
+    /// Returns the reasoning like [`reasoning`](Self::reasoning), but for models with
+    /// [`reasoning`](crate::ModelCapabilities::reasoning), everything up to a closing
+    /// `</think>` tag is reasoning even without an opening tag, since chat templates
+    /// may put the opening tag in the prompt.
+    ///
+    /// This is the reasoning that
+    /// [`with_reasoning_stripped`](ChatBuilder::with_reasoning_stripped) removes from
+    /// the responses of `model`.
+    pub fn reasoning_for(&self, model: &crate::Model) -> Option<&str> {
+        split_reasoning(self.content.as_deref()?, emits_reasoning(model))
+            .map(|(reasoning, _)| reasoning)
+    }
+
+    /// Returns the content without the reasoning like [`answer`](Self::answer), but
+    /// recognizes the reasoning of `model` like [`reasoning_for`](Self::reasoning_for).
+    pub fn answer_for(&self, model: &crate::Model) -> Option<&str> {
+        let content = self.content.as_deref()?;
+        Some(split_reasoning(content, emits_reasoning(model)).map_or(content, |(_, answer)| answer))
+    }
+
+    /// Removes the reasoning of `model` from the content, leaving no content if there
+    /// is no answer.
+    pub(crate) fn strip_reasoning(&mut self, model: &crate::Model) {
+        if self.reasoning_for(model).is_none() {
+            return;
+        }
+        let answer = self.answer_for(model).unwrap_or_default();
+        self.content = (!answer.is_empty()).then(|| answer.to_string());
+    }
+}
+
+/// Returns whether the model's capabilities include reasoning.
+fn emits_reasoning(model: &crate::Model) -> bool {
+    model
+        .capabilities()
+        .is_some_and(|capabilities| capabilities.reasoning)
+}
+
+/// Splits content that starts with a `<think>` block into the reasoning and the
+/// answer, or returns `None` if there is no such block.
+///
+/// With `open`, the opening tag may be missing. Content that has an opening tag but
+/// no closing one was cut off while reasoning, so it has no answer.
+fn split_reasoning(content: &str, open: bool) -> Option<(&str, &str)> {
+    const OPEN: &str = "<think>";
+    const CLOSE: &str = "</think>";
+
+    let (reasoning, answer) = match content.trim_start().strip_prefix(OPEN) {
+        Some(rest) => rest.split_once(CLOSE).unwrap_or((rest, "")),
+        None if open => content.split_once(CLOSE)?,
+        None => return None,
+    };
+    Some((reasoning.trim(), answer.trim()))
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ToolCall {
     pub id: String,
@@ -48,6 +231,127 @@ impl FunctionCall {
             .find(|arg| arg.name == argument)
             .map(|arg| arg.value.clone())
     }
+
+    /// Returns the value of an argument, parsed as `T`.
+    ///
+    /// Values that cannot be parsed as they are, such as numbers surrounded by
+    /// whitespace, are parsed again without the surrounding whitespace, so strings
+    /// are returned unchanged. Fails if the argument is missing or if its value
+    /// cannot be parsed.
+    pub fn get_as<T>(&self, argument: &str) -> Result<T, ArgumentError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self
+            .arguments
+            .iter()
+            .find(|arg| arg.name == argument)
+            .ok_or_else(|| ArgumentError::Missing(argument.to_string()))?;
+        arguments::parse_value(&value.value).map_err(|message| ArgumentError::Invalid {
+            name: argument.to_string(),
+            message,
+        })
+    }
+
+    /// Returns the value of an argument, deserialized as `T`.
+    ///
+    /// Unlike [`get_as`](Self::get_as), this also reads arrays and objects, which
+    /// the model passes as JSON, e.g. into a `Vec<String>` or a struct. Fails if the
+    /// argument is missing or if its value cannot be deserialized.
+    ///
+    /// ```
+    /// use ic_llm::FunctionCall;
+    ///
+    /// fn accounts(call: &FunctionCall) -> Result<Vec<String>, ic_llm::ArgumentError> {
+    ///     call.get_deserialized("accounts")
+    /// }
+    /// # let call = FunctionCall {
+    /// #     name: "balances".to_string(),
+    /// #     arguments: vec![ic_llm::ToolCallArgument {
+    /// #         name: "accounts".to_string(),
+    /// #         value: r#"["a", "b"]"#.to_string(),
+    /// #     }],
+    /// # };
+    /// # assert_eq!(accounts(&call), Ok(vec!["a".to_string(), "b".to_string()]));
+    /// ```
+    pub fn get_deserialized<T: DeserializeOwned>(
+        &self,
+        argument: &str,
+    ) -> Result<T, ArgumentError> {
+        let value = self
+            .arguments
+            .iter()
+            .find(|arg| arg.name == argument)
+            .ok_or_else(|| ArgumentError::Missing(argument.to_string()))?;
+        arguments::deserialize_value(value)
+    }
+
+    /// Deserializes all the arguments into `T`, typically a struct with a field per argument.
+    ///
+    /// Each value is parsed according to the type of the field it is assigned to, so that
+    /// numbers and booleans declared with [`ParameterType::Number`](crate::ParameterType::Number)
+    /// and [`ParameterType::Boolean`](crate::ParameterType::Boolean) can be read as such.
+    /// Arrays and objects, declared with [`ParameterType::Array`](crate::ParameterType::Array)
+    /// and [`ParameterType::Object`](crate::ParameterType::Object), are parsed from JSON.
+    /// `Option` fields are optional, and unit-only enums are parsed from the variant name.
+    ///
+    /// Only the field types are taken into account: fields that accept any type,
+    /// such as `serde_json::Value` or untagged enums, get the values as strings. Use
+    /// [`parse_with`](Self::parse_with) to honor the declared parameter types instead.
+    ///
+    /// ```
+    /// use ic_llm::FunctionCall;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct WeatherArgs {
+    ///     location: String,
+    ///     days: Option<u32>,
+    /// }
+    ///
+    /// fn handle(call: &FunctionCall) -> Result<String, ic_llm::ArgumentError> {
+    ///     let args: WeatherArgs = call.parse()?;
+    ///     Ok(format!("{} days in {}", args.days.unwrap_or(1), args.location))
+    /// }
+    /// ```
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, ArgumentError> {
+        T::deserialize(ArgumentsDeserializer::new(&self.arguments, &[]))
+    }
+
+    /// Deserializes all the arguments into `T` like [`parse`](Self::parse), but
+    /// passes fields that accept any type the values as declared by the parameters
+    /// of `tool`: numbers and booleans as such, and arrays and objects as JSON.
+    ///
+    /// ```
+    /// use ic_llm::{FunctionCall, ParameterType, Tool};
+    /// use serde_json::{json, Value};
+    ///
+    /// let tool = ic_llm::tool("search")
+    ///     .with_parameter(ic_llm::parameter("limit", ParameterType::Integer))
+    ///     .build();
+    ///
+    /// fn handle(tool: &Tool, call: &FunctionCall) -> Result<Value, ic_llm::ArgumentError> {
+    ///     call.parse_with(tool)
+    /// }
+    /// # let call = FunctionCall {
+    /// #     name: "search".to_string(),
+    /// #     arguments: vec![ic_llm::ToolCallArgument {
+    /// #         name: "limit".to_string(),
+    /// #         value: "10".to_string(),
+    /// #     }],
+    /// # };
+    /// # assert_eq!(handle(&tool, &call), Ok(json!({ "limit": 10 })));
+    /// ```
+    pub fn parse_with<T: DeserializeOwned>(&self, tool: &Tool) -> Result<T, ArgumentError> {
+        let Tool::Function(function) = tool;
+        let properties = function
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.properties.as_deref())
+            .unwrap_or_default();
+        T::deserialize(ArgumentsDeserializer::new(&self.arguments, properties))
+    }
 }
 
 /// An argument to be provided to a tool.
@@ -57,21 +361,51 @@ pub struct ToolCallArgument {
     pub value: String,
 }
 
-// Internal request type sent to the canister
-#[derive(CandidType, Serialize, Deserialize, Debug)]
-struct Request {
-    model: String,
-    messages: Vec<ChatMessage>,
-    tools: Option<Vec<Tool>>,
+/// A chat request, as sent to the LLM canister.
+///
+/// The tool choice and the sampling parameters are optional, so that versions of
+/// the LLM canister that don't support them, including the current one, ignore them.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Request {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
 }
 
 /// Builder for creating and sending chat requests to the LLM canister.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ChatBuilder {
     model: crate::Model,
     messages: Vec<ChatMessage>,
     tools: Vec<Tool>,
+    tool_choice: Option<ToolChoice>,
     canister: Principal,
+    retry_policy: RetryPolicy,
+    transport: Option<SharedTransport>,
+    timeout_secs: u32,
+    wait_mode: WaitMode,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    seed: Option<u64>,
+    stop: Option<Vec<String>>,
+    output_schema: Option<Parameters>,
+    repair_attempts: u32,
+    strip_reasoning: bool,
+    context_budget: Option<ContextBudget>,
+    tool_content_limit: Option<usize>,
 }
 
 impl ChatBuilder {
@@ -81,7 +415,22 @@ impl ChatBuilder {
             model,
             messages: Vec::new(),
             tools: Vec::new(),
+            tool_choice: None,
             canister: crate::default_llm_canister(),
+            retry_policy: RetryPolicy::never(),
+            transport: None,
+            timeout_secs: transport::DEFAULT_TIMEOUT_SECS,
+            wait_mode: WaitMode::Bounded,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            stop: None,
+            output_schema: None,
+            repair_attempts: structured::DEFAULT_REPAIR_ATTEMPTS,
+            strip_reasoning: false,
+            context_budget: None,
+            tool_content_limit: None,
         }
     }
 
@@ -97,6 +446,21 @@ impl ChatBuilder {
         self
     }
 
+    /// Sets whether and which tools the model must call.
+    ///
+    /// The choice is sent with the request, but the current LLM canister ignores
+    /// it. Therefore a response that doesn't comply is followed by a second
+    /// request that reminds the model of the choice in a system message. If that
+    /// response doesn't comply either, [`LlmError::ToolChoiceIgnored`] is returned.
+    ///
+    /// A choice that requires a tool call fails with
+    /// [`LlmError::ToolChoiceUnsatisfiable`] without sending the request if no
+    /// matching tool is offered.
+    pub fn with_tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
     /// Overrides the LLM canister to call.
     ///
     /// By default the SDK addresses the mainnet LLM canister
@@ -110,33 +474,295 @@ impl ChatBuilder {
         self
     }
 
+    /// Sets the sampling temperature. Lower values make the output more deterministic.
+    ///
+    /// Like the other sampling parameters, it is ignored by the current LLM
+    /// canister, whose interface doesn't include them yet.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Sets the nucleus sampling probability: only the most likely tokens whose
+    /// probabilities add up to `top_p` are considered.
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the maximum number of tokens to generate.
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the seed for sampling, so that repeated requests produce the same output.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets sequences at which the model stops generating.
+    pub fn with_stop<S: Into<String>, I: IntoIterator<Item = S>>(mut self, stop: I) -> Self {
+        self.stop = Some(stop.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the JSON schema that answers requested with
+    /// [`send_structured`](ChatBuilder::send_structured) must match.
+    pub fn with_output_schema(mut self, schema: Parameters) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    /// Sets the output schema to the JSON schema of `T`.
+    ///
+    /// Fails if the schema of `T` is not an object, or uses constructs that cannot
+    /// be represented, such as untagged enums.
+    #[cfg(feature = "schemars")]
+    pub fn with_output_schema_from<T: schemars::JsonSchema>(
+        self,
+    ) -> Result<Self, crate::SchemaError> {
+        Ok(self.with_output_schema(crate::json_schema::parameters_from::<T>()?))
+    }
+
+    /// Sets how many times [`send_structured`](ChatBuilder::send_structured) asks the
+    /// model to correct an answer that cannot be parsed. Defaults to 2.
+    pub fn with_repair_attempts(mut self, repair_attempts: u32) -> Self {
+        self.repair_attempts = repair_attempts;
+        self
+    }
+
+    /// Removes the reasoning from the content of responses, so that only the
+    /// [`answer`](AssistantMessage::answer) ends up in the conversation history.
+    pub fn with_reasoning_stripped(mut self) -> Self {
+        self.strip_reasoning = true;
+        self
+    }
+
+    /// Fits the messages of requests into the given budget of tokens.
+    ///
+    /// System messages are always kept. The oldest other messages are left out of
+    /// requests until the rest fits, but the latest message is always kept and tool
+    /// messages are only left out together with the tool calls they answer. If the
+    /// remaining messages still don't fit, their contents are truncated.
+    pub fn with_context_budget(mut self, budget: ContextBudget) -> Self {
+        self.context_budget = Some(budget);
+        self
+    }
+
+    /// Truncates the contents of tool messages to `max_bytes`, including a
+    /// `" [truncated]"` marker, if the request would otherwise exceed the size limit
+    /// of inter-canister messages.
+    ///
+    /// Requests that exceed the limit fail with [`LlmError::RequestTooLarge`], which
+    /// reports the largest messages. Tool results, such as fetched web pages, are
+    /// usually the culprits. The size is checked by the [`CanisterTransport`] when
+    /// it encodes the request, so it doesn't apply to other transports.
+    pub fn with_tool_content_limit(mut self, max_bytes: usize) -> Self {
+        self.tool_content_limit = Some(max_bytes);
+        self
+    }
+
+    /// Sets the policy for retrying failed requests.
+    ///
+    /// By default, failed requests are not retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets how many seconds the call to the LLM canister waits for a response.
+    /// Defaults to 300.
+    ///
+    /// When the timeout expires, [`LlmError::Timeout`] is returned, although the LLM
+    /// canister may still process the request. Has no effect with
+    /// [`WaitMode::Unbounded`] or a custom transport.
+    pub fn with_timeout(mut self, timeout_secs: u32) -> Self {
+        self.timeout_secs = timeout_secs;
+        self
+    }
+
+    /// Sets how long the call to the LLM canister waits for its response.
+    /// Defaults to [`WaitMode::Bounded`].
+    ///
+    /// Has no effect with a custom transport.
+    pub fn with_wait_mode(mut self, wait_mode: WaitMode) -> Self {
+        self.wait_mode = wait_mode;
+        self
+    }
+
+    /// Sends the request through the given transport instead of calling the LLM canister.
+    ///
+    /// This takes precedence over a transport set with [`set_transport`](crate::set_transport).
+    pub fn with_transport<T: LlmTransport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(SharedTransport::new(transport));
+        self
+    }
+
+    pub(crate) fn model(&self) -> &crate::Model {
+        &self.model
+    }
+
     /// Sends the chat request to the LLM canister.
+    ///
+    /// Traps if the request fails. Use [`ChatBuilder::try_send`] to handle errors instead.
     pub async fn send(self) -> Response {
+        self.try_send()
+            .await
+            .unwrap_or_else(|e| ic_cdk::trap(e.to_string()))
+    }
+
+    /// Sends the chat request to the LLM canister, returning an error if the request fails.
+    pub async fn try_send(self) -> Result<Response, LlmError> {
+        let strip_reasoning = self.strip_reasoning.then(|| self.model.clone());
+        let mut response = self.dispatch().await?;
+        if let Some(model) = strip_reasoning {
+            response.message.strip_reasoning(&model);
+        }
+        Ok(response)
+    }
+
+    async fn dispatch(self) -> Result<Response, LlmError> {
+        if let Some(tool_choice) = &self.tool_choice {
+            if !tool_choice.is_satisfiable_with(&self.tools) {
+                return Err(LlmError::ToolChoiceUnsatisfiable(tool_choice.clone()));
+            }
+        }
+
+        let messages = match self
+            .context_budget
+            .and_then(|budget| budget.for_messages(&self.model, &self.tools, self.max_tokens))
+        {
+            Some(budget) => context::fit(&self.model, self.messages, budget),
+            None => self.messages,
+        };
+
         let tools_option = if self.tools.is_empty() {
             None
         } else {
             Some(self.tools)
         };
 
-        ic_cdk::call::Call::bounded_wait(self.canister, "v1_chat")
-            .change_timeout(300)
-            .with_arg(Request {
-                model: self.model.to_string(),
-                messages: self.messages,
-                tools: tools_option,
-            })
-            .await
-            .unwrap_or_else(|e| ic_cdk::trap(format!("LLM call failed: {e:?}")))
-            .candid()
-            .unwrap_or_else(|e| ic_cdk::trap(format!("failed to decode LLM response: {e:?}")))
+        let mut request = Request {
+            model: self.model.to_string(),
+            messages,
+            tools: tools_option,
+            tool_choice: self.tool_choice.clone(),
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            seed: self.seed,
+            stop: self.stop,
+        };
+        let transport = self
+            .transport
+            .or_else(transport::global_transport)
+            .unwrap_or_else(|| {
+                let mut transport = CanisterTransport::new(self.canister)
+                    .with_timeout(self.timeout_secs)
+                    .with_wait_mode(self.wait_mode);
+                if let Some(max_bytes) = self.tool_content_limit {
+                    transport = transport.with_tool_content_limit(max_bytes);
+                }
+                SharedTransport::new(transport)
+            });
+
+        let send = |request: Request| {
+            let policy = &self.retry_policy;
+            let transport = &transport;
+            async move {
+                retry::with_retries(
+                    policy,
+                    || transport.chat(request.clone()),
+                    |delay| transport.wait(delay),
+                )
+                .await
+            }
+        };
+
+        let response = send(request.clone()).await?;
+        let Some(tool_choice) = self.tool_choice else {
+            return Ok(response);
+        };
+        if tool_choice.is_satisfied_by(&response.message) {
+            return Ok(response);
+        }
+        let Some(instruction) = tool_choice.instruction() else {
+            return Ok(response);
+        };
+
+        // The choice was ignored, so spell it out for the model.
+        request.messages.push(ChatMessage::System {
+            content: instruction,
+        });
+        let response = send(request).await?;
+        if tool_choice.is_satisfied_by(&response.message) {
+            Ok(response)
+        } else {
+            Err(LlmError::ToolChoiceIgnored(tool_choice))
+        }
+    }
+
+    /// Asks the model to answer with JSON and deserializes the answer as `T`.
+    ///
+    /// The JSON is taken from a code fence if there is one, or else from the first
+    /// JSON value in the answer, and is checked against the output schema if one is
+    /// set. If the answer cannot be used, the model is told what is wrong with it and
+    /// asked again, up to the number of repair attempts. If none of the answers can
+    /// be used, [`LlmError::InvalidOutput`] is returned.
+    pub async fn send_structured<T: DeserializeOwned>(self) -> Result<T, LlmError> {
+        let schema = self.output_schema.clone();
+        let attempts = self.repair_attempts.saturating_add(1);
+        let mut messages = self.messages.clone();
+        messages.push(ChatMessage::System {
+            content: structured::instruction(schema.as_ref()),
+        });
+
+        let mut attempt = 1;
+        loop {
+            let message = self
+                .clone()
+                .with_messages(messages.clone())
+                .try_send()
+                .await?
+                .message;
+            let content = message.answer_for(&self.model).unwrap_or_default();
+            let error = match structured::parse(content, schema.as_ref()) {
+                Ok(output) => return Ok(output),
+                Err(error) if attempt == attempts => {
+                    return Err(LlmError::InvalidOutput { attempts, error })
+                }
+                Err(error) => error,
+            };
+            messages.push(ChatMessage::Assistant(message));
+            messages.push(ChatMessage::User {
+                content: structured::repair_prompt(&error),
+            });
+            attempt += 1;
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tool::ToolBuilder;
+    use crate::testing::{block_on, text_response, tool_call, MockLlm};
+    use crate::tool::{ParameterBuilder, ParameterType, ToolBuilder};
     use crate::Model;
+    use std::time::Duration;
+
+    /// A mock that answers requests with the given results, in order.
+    fn scripted(results: Vec<Result<Response, LlmError>>) -> MockLlm {
+        let mock = MockLlm::new();
+        for result in results {
+            match result {
+                Ok(response) => mock.reply(response),
+                Err(error) => mock.fail(error),
+            };
+        }
+        mock
+    }
 
     #[test]
     fn create_chat_builder() {
@@ -191,6 +817,28 @@ mod tests {
         assert_eq!(builder.canister, canister);
     }
 
+    #[test]
+    fn chat_builder_with_retry_policy() {
+        let builder = ChatBuilder::new(Model::Llama3_1_8B);
+        assert_eq!(builder.retry_policy, RetryPolicy::never());
+
+        let builder = builder.with_retry_policy(RetryPolicy::default());
+        assert_eq!(builder.retry_policy, RetryPolicy::default());
+    }
+
+    #[test]
+    fn chat_builder_with_timeout_and_wait_mode() {
+        let builder = ChatBuilder::new(Model::Qwen3_32B);
+        assert_eq!(builder.timeout_secs, 300);
+        assert_eq!(builder.wait_mode, WaitMode::Bounded);
+
+        let builder = builder
+            .with_timeout(900)
+            .with_wait_mode(WaitMode::Unbounded);
+        assert_eq!(builder.timeout_secs, 900);
+        assert_eq!(builder.wait_mode, WaitMode::Unbounded);
+    }
+
     #[test]
     fn chat_builder_with_messages_and_tools() {
         let messages = vec![ChatMessage::User {
@@ -208,6 +856,338 @@ mod tests {
         assert_eq!(builder.tools[0], tool);
     }
 
+    #[test]
+    fn try_send_dispatches_through_transport() {
+        let transport = scripted(vec![Ok(text_response("Hi there"))]);
+        let tool = ToolBuilder::new("test_tool").build();
+
+        let response = block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_messages(vec![ChatMessage::User {
+                    content: "Hello".to_string(),
+                }])
+                .with_tools(vec![tool.clone()])
+                .with_transport(transport.clone())
+                .try_send(),
+        )
+        .unwrap();
+
+        assert_eq!(response.message.content, Some("Hi there".to_string()));
+        assert_eq!(
+            transport.requests().as_slice(),
+            &[Request {
+                model: "llama3.1:8b".to_string(),
+                messages: vec![ChatMessage::User {
+                    content: "Hello".to_string(),
+                }],
+                tools: Some(vec![tool]),
+                ..Default::default()
+            }]
+        );
+    }
+
+    fn tool_calls_response(names: &[&str]) -> Response {
+        Response {
+            message: AssistantMessage {
+                content: None,
+                tool_calls: names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| tool_call(&format!("call_{i}"), name, &[]))
+                    .collect(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn send_with_tool_choice(
+        tool_choice: ToolChoice,
+        results: Vec<Result<Response, LlmError>>,
+    ) -> (Result<Response, LlmError>, Vec<Request>) {
+        let transport = scripted(results);
+        let result = block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_messages(vec![ChatMessage::User {
+                    content: "Weather in Cairo?".to_string(),
+                }])
+                .with_tools(vec![
+                    ToolBuilder::new("get_weather").build(),
+                    ToolBuilder::new("get_time").build(),
+                ])
+                .with_tool_choice(tool_choice)
+                .with_transport(transport.clone())
+                .try_send(),
+        );
+        let requests = transport.requests();
+        (result, requests)
+    }
+
+    #[test]
+    fn try_send_includes_tool_choice() {
+        let (result, requests) = send_with_tool_choice(
+            ToolChoice::function("get_weather"),
+            vec![Ok(tool_calls_response(&["get_weather"]))],
+        );
+
+        assert_eq!(result, Ok(tool_calls_response(&["get_weather"])));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].tool_choice,
+            Some(ToolChoice::function("get_weather"))
+        );
+        assert_eq!(
+            serde_json::to_value(&requests[0].tool_choice).unwrap(),
+            serde_json::json!({ "function": "get_weather" })
+        );
+    }
+
+    #[test]
+    fn try_send_rejects_tool_choices_without_matching_tools() {
+        let (result, requests) = send_with_tool_choice(ToolChoice::function("get_news"), vec![]);
+        assert_eq!(
+            result,
+            Err(LlmError::ToolChoiceUnsatisfiable(ToolChoice::function(
+                "get_news"
+            )))
+        );
+        assert!(requests.is_empty());
+
+        let transport = scripted(vec![]);
+        let result = block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_tool_choice(ToolChoice::Required)
+                .with_transport(transport.clone())
+                .try_send(),
+        );
+        assert_eq!(
+            result,
+            Err(LlmError::ToolChoiceUnsatisfiable(ToolChoice::Required))
+        );
+        assert!(transport.requests().is_empty());
+    }
+
+    #[test]
+    fn try_send_reminds_the_model_of_an_ignored_tool_choice() {
+        let (result, requests) = send_with_tool_choice(
+            ToolChoice::None,
+            vec![
+                Ok(tool_calls_response(&["get_weather"])),
+                Ok(text_response("It's sunny.")),
+            ],
+        );
+
+        assert_eq!(result, Ok(text_response("It's sunny.")));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].tools, requests[0].tools);
+        assert_eq!(
+            requests[1].messages.last(),
+            Some(&ChatMessage::System {
+                content: "Do not call any tools. Answer the user directly.".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn try_send_fails_if_the_tool_choice_is_ignored_again() {
+        let (result, requests) = send_with_tool_choice(
+            ToolChoice::function("get_weather"),
+            vec![
+                Ok(tool_calls_response(&["get_time"])),
+                Ok(tool_calls_response(&["get_weather", "get_time"])),
+            ],
+        );
+
+        assert_eq!(
+            result,
+            Err(LlmError::ToolChoiceIgnored(ToolChoice::function(
+                "get_weather"
+            )))
+        );
+        assert_eq!(requests.len(), 2);
+
+        let (result, _) = send_with_tool_choice(
+            ToolChoice::Required,
+            vec![Ok(text_response("Sunny.")), Ok(text_response("Sunny."))],
+        );
+        assert_eq!(
+            result,
+            Err(LlmError::ToolChoiceIgnored(ToolChoice::Required))
+        );
+    }
+
+    #[test]
+    fn response_metadata_is_optional() {
+        #[derive(CandidType, Deserialize)]
+        struct LegacyResponse {
+            message: AssistantMessage,
+        }
+
+        let bytes = candid::encode_one(LegacyResponse {
+            message: text_response("Hi").message,
+        })
+        .unwrap();
+        assert_eq!(
+            candid::decode_one::<Response>(&bytes).unwrap(),
+            text_response("Hi")
+        );
+        assert_eq!(
+            serde_json::from_str::<Response>(r#"{"message":{"content":"Hi","tool_calls":[]}}"#)
+                .unwrap(),
+            text_response("Hi")
+        );
+    }
+
+    #[test]
+    fn response_metadata_is_decoded() {
+        let response = Response {
+            usage: Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 4,
+                total_tokens: 16,
+            }),
+            finish_reason: Some(FinishReason::Length),
+            model: Some("llama3.1:8b".to_string()),
+            ..text_response("Hi")
+        };
+
+        let bytes = candid::encode_one(&response).unwrap();
+        let decoded = candid::decode_one::<Response>(&bytes).unwrap();
+        assert_eq!(decoded, response);
+        assert!(decoded.is_truncated());
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["finish_reason"], "length");
+        assert_eq!(
+            serde_json::from_value::<FinishReason>(serde_json::json!("end_turn")).unwrap(),
+            FinishReason::Other("end_turn".to_string())
+        );
+    }
+
+    #[test]
+    fn try_send_includes_sampling_parameters() {
+        let transport = scripted(vec![Ok(text_response("positive"))]);
+
+        block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_temperature(0.0)
+                .with_top_p(0.9)
+                .with_max_tokens(16)
+                .with_seed(42)
+                .with_stop(["\n"])
+                .with_transport(transport.clone())
+                .try_send(),
+        )
+        .unwrap();
+
+        let request = transport.requests()[0].clone();
+        assert_eq!(
+            request,
+            Request {
+                model: "llama3.1:8b".to_string(),
+                temperature: Some(0.0),
+                top_p: Some(0.9),
+                max_tokens: Some(16),
+                seed: Some(42),
+                stop: Some(vec!["\n".to_string()]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unset_sampling_parameters_are_omitted() {
+        #[derive(CandidType, Deserialize, Debug, PartialEq)]
+        struct LegacyRequest {
+            model: String,
+            messages: Vec<ChatMessage>,
+            tools: Option<Vec<Tool>>,
+        }
+
+        let request = Request {
+            model: "llama3.1:8b".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"model":"llama3.1:8b","messages":[],"tools":null}"#
+        );
+
+        // Canisters that don't know the sampling parameters ignore them.
+        let request = Request {
+            seed: Some(42),
+            ..request
+        };
+        let bytes = candid::encode_one(&request).unwrap();
+        assert_eq!(
+            candid::decode_one::<LegacyRequest>(&bytes).unwrap(),
+            LegacyRequest {
+                model: "llama3.1:8b".to_string(),
+                messages: vec![],
+                tools: None,
+            }
+        );
+    }
+
+    #[test]
+    fn try_send_uses_global_transport() {
+        let transport = scripted(vec![Err(LlmError::CanisterError(
+            "out of workers".to_string(),
+        ))]);
+        transport::set_transport(transport.clone());
+
+        let result = block_on(ChatBuilder::new(Model::Qwen3_32B).try_send());
+        transport::clear_transport();
+
+        assert_eq!(
+            result.unwrap_err(),
+            LlmError::CanisterError("out of workers".to_string())
+        );
+        assert_eq!(transport.requests()[0].model, "qwen3:32b");
+        assert_eq!(transport.requests()[0].tools, None);
+    }
+
+    #[test]
+    fn try_send_retries_through_transport() {
+        let transport = scripted(vec![
+            Err(LlmError::Transient("busy".to_string())),
+            Ok(text_response("done")),
+        ]);
+
+        let response = block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_retry_policy(RetryPolicy::default())
+                .with_transport(transport.clone())
+                .try_send(),
+        )
+        .unwrap();
+
+        assert_eq!(response.message.content, Some("done".to_string()));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn try_send_waits_between_retries() {
+        let mock = MockLlm::new();
+        mock.fail(LlmError::Transient("busy".to_string()))
+            .fail(LlmError::Timeout("slow".to_string()))
+            .reply(text_response("done"));
+
+        let response = block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_retry_policy(RetryPolicy::new(3).with_delay(Duration::from_secs(2)))
+                .with_transport(mock.clone())
+                .try_send(),
+        )
+        .unwrap();
+
+        assert_eq!(response.message.content, Some("done".to_string()));
+        assert_eq!(
+            mock.waits(),
+            vec![Duration::from_secs(2), Duration::from_secs(4)]
+        );
+        mock.assert_done();
+    }
+
     #[test]
     fn function_call_get() {
         let function_call = FunctionCall {
@@ -228,4 +1208,420 @@ mod tests {
         assert_eq!(function_call.get("arg2"), Some("value2".to_string()));
         assert_eq!(function_call.get("arg3"), None);
     }
+
+    #[test]
+    fn function_call_get_as() {
+        let function_call = tool_call(
+            "call_1",
+            "convert",
+            &[
+                ("value", " 2.5\n"),
+                ("to_feet", "true"),
+                ("unit", "m"),
+                ("label", "  indented "),
+                ("separator", " "),
+            ],
+        )
+        .function;
+
+        assert_eq!(function_call.get_as::<f64>("value"), Ok(2.5));
+        assert_eq!(function_call.get_as::<bool>("to_feet"), Ok(true));
+        assert_eq!(
+            function_call.get_as::<String>("label"),
+            Ok("  indented ".to_string())
+        );
+        assert_eq!(function_call.get_as::<char>("separator"), Ok(' '));
+        assert_eq!(
+            function_call.get_as::<u32>("count"),
+            Err(ArgumentError::Missing("count".to_string()))
+        );
+        assert_eq!(
+            function_call.get_as::<bool>("unit"),
+            Err(ArgumentError::Invalid {
+                name: "unit".to_string(),
+                message: "provided string was not `true` or `false`: \"m\"".to_string(),
+            })
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Unit {
+        Meters,
+        Feet,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct ConvertArgs {
+        value: f64,
+        unit: Unit,
+        precision: Option<u8>,
+        label: Option<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Range {
+        from: u64,
+        to: Option<u64>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct SearchArgs {
+        accounts: Vec<String>,
+        range: Range,
+    }
+
+    #[test]
+    fn function_call_parse_json_arguments() {
+        let parse = |arguments: &[(&str, &str)]| {
+            tool_call("call_1", "search", arguments)
+                .function
+                .parse::<SearchArgs>()
+        };
+
+        assert_eq!(
+            parse(&[("accounts", r#"["a", "b"]"#), ("range", r#"{"from": 1}"#)]),
+            Ok(SearchArgs {
+                accounts: vec!["a".to_string(), "b".to_string()],
+                range: Range { from: 1, to: None },
+            })
+        );
+        assert!(matches!(
+            parse(&[("accounts", "a, b"), ("range", r#"{"from": 1}"#)]),
+            Err(ArgumentError::Invalid { name, .. }) if name == "accounts"
+        ));
+        assert!(matches!(
+            parse(&[("accounts", "[]"), ("range", r#"{"from": 1} x"#)]),
+            Err(ArgumentError::Invalid { name, .. }) if name == "range"
+        ));
+    }
+
+    #[test]
+    fn function_call_parse() {
+        let function_call = tool_call(
+            "call_1",
+            "convert",
+            &[("value", " 3 "), ("unit", "feet"), ("precision", "2")],
+        )
+        .function;
+
+        assert_eq!(
+            function_call.parse::<ConvertArgs>(),
+            Ok(ConvertArgs {
+                value: 3.0,
+                unit: Unit::Feet,
+                precision: Some(2),
+                label: None,
+            })
+        );
+    }
+
+    #[test]
+    fn function_call_parse_with_declared_types() {
+        let tool = ToolBuilder::new("search")
+            .with_parameter(ParameterBuilder::new("limit", ParameterType::Integer))
+            .with_parameter(ParameterBuilder::new("min_score", ParameterType::Number))
+            .with_parameter(ParameterBuilder::new("exact", ParameterType::Boolean))
+            .with_parameter(ParameterBuilder::new(
+                "accounts",
+                ParameterType::array_of(ParameterType::String),
+            ))
+            .build();
+        let function_call = tool_call(
+            "call_1",
+            "search",
+            &[
+                ("limit", "10"),
+                ("min_score", "0.5"),
+                ("exact", "true"),
+                ("accounts", r#"["a"]"#),
+                ("query", "42"),
+            ],
+        )
+        .function;
+
+        let expected = serde_json::json!({
+            "limit": 10,
+            "min_score": 0.5,
+            "exact": true,
+            "accounts": ["a"],
+            "query": "42",
+        });
+        assert_eq!(
+            function_call.parse_with::<serde_json::Value>(&tool),
+            Ok(expected)
+        );
+        assert_eq!(
+            function_call.parse::<serde_json::Value>().unwrap()["limit"],
+            "10"
+        );
+        assert!(matches!(
+            tool_call("call_1", "search", &[("limit", "ten")])
+                .function
+                .parse_with::<serde_json::Value>(&tool),
+            Err(ArgumentError::Invalid { name, .. }) if name == "limit"
+        ));
+    }
+
+    #[test]
+    fn function_call_parse_errors_name_the_argument() {
+        let parse = |arguments: &[(&str, &str)]| {
+            tool_call("call_1", "convert", arguments)
+                .function
+                .parse::<ConvertArgs>()
+        };
+
+        assert_eq!(
+            parse(&[("unit", "feet")]),
+            Err(ArgumentError::Missing("value".to_string()))
+        );
+        assert_eq!(
+            parse(&[("value", "3"), ("unit", "feet"), ("precision", "-1")]),
+            Err(ArgumentError::Invalid {
+                name: "precision".to_string(),
+                message: "invalid digit found in string: \"-1\"".to_string(),
+            })
+        );
+        assert!(matches!(
+            parse(&[("value", "3"), ("unit", "miles")]),
+            Err(ArgumentError::Invalid { name, .. }) if name == "unit"
+        ));
+
+        #[derive(Deserialize, Debug)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Strict {
+            value: f64,
+        }
+        assert_eq!(
+            tool_call("call_1", "convert", &[("value", "3"), ("scale", "2")])
+                .function
+                .parse::<Strict>()
+                .unwrap_err(),
+            ArgumentError::Unexpected("scale".to_string())
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Sentiment {
+        label: String,
+    }
+
+    fn send_structured(
+        builder: ChatBuilder,
+        results: Vec<Result<Response, LlmError>>,
+    ) -> (Result<Sentiment, LlmError>, Vec<Request>) {
+        let transport = scripted(results);
+        let result = block_on(
+            builder
+                .with_messages(vec![ChatMessage::User {
+                    content: "I love it!".to_string(),
+                }])
+                .with_output_schema(Parameters {
+                    type_: "object".to_string(),
+                    properties: Some(vec![crate::Property {
+                        type_: "string".to_string(),
+                        name: "label".to_string(),
+                        enum_: Some(vec!["positive".to_string(), "negative".to_string()]),
+                        ..Default::default()
+                    }]),
+                    required: Some(vec!["label".to_string()]),
+                })
+                .with_transport(transport.clone())
+                .send_structured(),
+        );
+        let requests = transport.requests();
+        (result, requests)
+    }
+
+    #[test]
+    fn send_structured_parses_the_answer() {
+        let (result, requests) = send_structured(
+            ChatBuilder::new(Model::Llama3_1_8B),
+            vec![Ok(text_response("```json\n{\"label\": \"positive\"}\n```"))],
+        );
+
+        assert_eq!(
+            result,
+            Ok(Sentiment {
+                label: "positive".to_string()
+            })
+        );
+        assert_eq!(requests.len(), 1);
+        let Some(ChatMessage::System { content }) = requests[0].messages.last() else {
+            panic!("expected a system message");
+        };
+        assert!(content.contains(r#""enum":["positive","negative"]"#));
+    }
+
+    #[test]
+    fn send_structured_asks_for_corrections() {
+        let (result, requests) = send_structured(
+            ChatBuilder::new(Model::Llama3_1_8B),
+            vec![
+                Ok(text_response(r#"{"label": "great"}"#)),
+                Ok(text_response(r#"{"label": "positive"}"#)),
+            ],
+        );
+
+        assert_eq!(
+            result,
+            Ok(Sentiment {
+                label: "positive".to_string()
+            })
+        );
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].messages[requests[0].messages.len()..],
+            [
+                ChatMessage::Assistant(text_response(r#"{"label": "great"}"#).message),
+                ChatMessage::User {
+                    content: "Your previous answer could not be used: `/label` must be one of: \
+                              positive, negative. Answer again with only the corrected JSON."
+                        .to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn send_structured_fails_after_the_repair_attempts() {
+        let (result, requests) = send_structured(
+            ChatBuilder::new(Model::Llama3_1_8B).with_repair_attempts(1),
+            vec![Ok(text_response("Positive!")), Ok(text_response("{}"))],
+        );
+
+        assert_eq!(
+            result,
+            Err(LlmError::InvalidOutput {
+                attempts: 2,
+                error: "missing required field `/label`".to_string(),
+            })
+        );
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn send_structured_allows_unlimited_repair_attempts() {
+        let (result, requests) = send_structured(
+            ChatBuilder::new(Model::Llama3_1_8B).with_repair_attempts(u32::MAX),
+            vec![
+                Ok(text_response("Positive!")),
+                Ok(text_response(r#"{"label": "positive"}"#)),
+            ],
+        );
+
+        assert_eq!(
+            result,
+            Ok(Sentiment {
+                label: "positive".to_string()
+            })
+        );
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn assistant_message_splits_reasoning_from_the_answer() {
+        let message = |content: &str| text_response(content).message;
+
+        let thinking = message("<think>\nThe user greets me.\n</think>\n\nHello!");
+        assert_eq!(thinking.reasoning(), Some("The user greets me."));
+        assert_eq!(thinking.answer(), Some("Hello!"));
+
+        // Without an opening tag, the content may well be the answer.
+        let closing = message("Use <think> and </think> tags.");
+        assert_eq!(closing.reasoning(), None);
+        assert_eq!(closing.answer(), Some("Use <think> and </think> tags."));
+
+        let truncated = message("<think>The user greets");
+        assert_eq!(truncated.reasoning(), Some("The user greets"));
+        assert_eq!(truncated.answer(), Some(""));
+
+        let plain = message("  Hello!\n");
+        assert_eq!(plain.reasoning(), None);
+        assert_eq!(plain.answer(), Some("  Hello!\n"));
+
+        assert_eq!(AssistantMessage::default().reasoning(), None);
+        assert_eq!(AssistantMessage::default().answer(), None);
+    }
+
+    #[test]
+    fn try_send_strips_reasoning() {
+        let mut reasoning_and_call = tool_calls_response(&["get_weather"]);
+        reasoning_and_call.message.content = Some("<think>I need the weather.</think>".into());
+        let transport = scripted(vec![
+            Ok(text_response("<think>Easy.</think>\nIt's sunny.")),
+            Ok(reasoning_and_call),
+            Ok(text_response("<think>Easy.</think>\nIt's sunny.")),
+        ]);
+        let send = |builder: ChatBuilder| {
+            block_on(builder.with_transport(transport.clone()).try_send())
+                .unwrap()
+                .message
+        };
+
+        let builder = ChatBuilder::new(Model::Qwen3_32B).with_reasoning_stripped();
+        assert_eq!(
+            send(builder.clone()).content,
+            Some("It's sunny.".to_string())
+        );
+        assert_eq!(send(builder), tool_calls_response(&["get_weather"]).message);
+        assert_eq!(
+            send(ChatBuilder::new(Model::Qwen3_32B)).content,
+            Some("<think>Easy.</think>\nIt's sunny.".to_string())
+        );
+    }
+
+    #[test]
+    fn strips_reasoning_without_opening_tag_for_reasoning_models() {
+        let mut message = text_response("The user greets me.</think>\nHello!").message;
+        message.strip_reasoning(&Model::Llama3_1_8B);
+        assert_eq!(
+            message.content.as_deref(),
+            Some("The user greets me.</think>\nHello!")
+        );
+        assert_eq!(message.reasoning(), None);
+        assert_eq!(message.reasoning_for(&Model::Llama3_1_8B), None);
+        assert_eq!(
+            message.reasoning_for(&Model::Qwen3_32B),
+            Some("The user greets me.")
+        );
+        assert_eq!(message.answer_for(&Model::Qwen3_32B), Some("Hello!"));
+        message.strip_reasoning(&Model::Qwen3_32B);
+        assert_eq!(message.content.as_deref(), Some("Hello!"));
+
+        let mut plain = text_response(" Hello! ").message;
+        plain.strip_reasoning(&Model::Qwen3_32B);
+        assert_eq!(plain.content.as_deref(), Some(" Hello! "));
+    }
+
+    #[test]
+    fn try_send_fits_messages_into_the_context_budget() {
+        let transport = scripted(vec![Ok(text_response("Rome."))]);
+        let messages = vec![
+            ChatMessage::System {
+                content: "Be brief".to_string(),
+            },
+            ChatMessage::User {
+                content: "What's the capital of France? ".repeat(100),
+            },
+            ChatMessage::User {
+                content: "And of Italy?".to_string(),
+            },
+        ];
+
+        block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_messages(messages.clone())
+                .with_context_budget(ContextBudget::Tokens(100))
+                .with_transport(transport.clone())
+                .try_send(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            transport.requests()[0].messages,
+            [messages[0].clone(), messages[2].clone()]
+        );
+    }
 }
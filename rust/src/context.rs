@@ -0,0 +1,329 @@
+use crate::chat::ChatMessage;
+use crate::tool::Tool;
+use crate::Model;
+use std::collections::HashSet;
+
+/// The number of tokens that the chat template adds to every message, e.g. for the role.
+const MESSAGE_OVERHEAD: u64 = 4;
+
+/// Appended to the content of messages that were cut short to fit the budget.
+const TRUNCATION_MARKER: &str = " [truncated]";
+
+/// How many tokens a request may take, as set with
+/// [`ChatBuilder::with_context_budget`](crate::ChatBuilder::with_context_budget).
+///
+/// Tokens are estimated with [`Model::estimate_tokens`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextBudget {
+    /// The context window of the model, minus the tokens reserved for the response:
+    /// the maximum number of tokens if set, or else the model's maximum output.
+    ///
+    /// Has no effect for models without [capabilities](Model::capabilities).
+    ModelWindow,
+    /// A fixed number of tokens for the messages and tools of a request.
+    Tokens(u64),
+}
+
+impl ContextBudget {
+    /// Returns the number of tokens available for the messages of a request, if known.
+    pub(crate) fn for_messages(
+        &self,
+        model: &Model,
+        tools: &[Tool],
+        max_tokens: Option<u32>,
+    ) -> Option<u64> {
+        let budget = match self {
+            ContextBudget::ModelWindow => {
+                let capabilities = model.capabilities()?;
+                let reserved = max_tokens.map_or(capabilities.max_output_tokens, u64::from);
+                capabilities.context_window.saturating_sub(reserved)
+            }
+            ContextBudget::Tokens(tokens) => *tokens,
+        };
+        let tools = if tools.is_empty() {
+            0
+        } else {
+            model.estimate_tokens(&serde_json::to_string(tools).unwrap_or_default())
+        };
+        Some(budget.saturating_sub(tools))
+    }
+}
+
+/// Fits messages into `budget` tokens.
+///
+/// System messages are always kept. The oldest other messages are dropped until the
+/// rest fits, but the latest message is always kept, and an assistant message with
+/// tool calls is only dropped together with the tool messages that answer them. If
+/// the kept messages still don't fit, their contents are truncated, oldest first.
+pub(crate) fn fit(model: &Model, mut messages: Vec<ChatMessage>, budget: u64) -> Vec<ChatMessage> {
+    let costs: Vec<u64> = messages.iter().map(|m| estimate(model, m)).collect();
+    let mut total: u64 = costs.iter().sum();
+    if total <= budget {
+        return messages;
+    }
+
+    // Group each assistant message with tool calls and the tool messages answering
+    // them, so that they are dropped together.
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut pending_calls = HashSet::new();
+    for (i, message) in messages.iter().enumerate() {
+        match message {
+            ChatMessage::System { .. } => continue,
+            ChatMessage::Tool { tool_call_id, .. } if pending_calls.remove(tool_call_id) => {
+                groups
+                    .last_mut()
+                    .expect("pending calls have a group")
+                    .push(i);
+                continue;
+            }
+            ChatMessage::Assistant(assistant) => {
+                pending_calls = assistant.tool_calls.iter().map(|c| c.id.clone()).collect();
+            }
+            _ => pending_calls.clear(),
+        }
+        groups.push(vec![i]);
+    }
+
+    let mut dropped = vec![false; messages.len()];
+    let droppable = groups.len().saturating_sub(1);
+    for group in groups.iter().take(droppable) {
+        if total <= budget {
+            break;
+        }
+        for &i in group {
+            dropped[i] = true;
+            total -= costs[i];
+        }
+    }
+
+    for (i, message) in messages.iter_mut().enumerate() {
+        if total <= budget {
+            break;
+        }
+        if dropped[i] || matches!(message, ChatMessage::System { .. }) {
+            continue;
+        }
+        if let Some(content) = content_mut(message) {
+            let tokens = model.estimate_tokens(content);
+            let excess = total - budget;
+            let keep = tokens.saturating_sub(excess);
+            truncate(
+                content,
+                (content.len() as u64 * keep / tokens.max(1)) as usize,
+            );
+            total = total - tokens + model.estimate_tokens(content);
+        }
+    }
+
+    messages
+        .into_iter()
+        .zip(dropped)
+        .filter(|(_, dropped)| !dropped)
+        .map(|(message, _)| message)
+        .collect()
+}
+
+/// Estimates the number of tokens of a message.
+pub(crate) fn estimate(model: &Model, message: &ChatMessage) -> u64 {
+    let tokens = match message {
+        ChatMessage::System { content }
+        | ChatMessage::User { content }
+        | ChatMessage::Tool { content, .. } => model.estimate_tokens(content),
+        ChatMessage::Assistant(assistant) => {
+            let content = model.estimate_tokens(assistant.content.as_deref().unwrap_or_default());
+            let calls: u64 = assistant
+                .tool_calls
+                .iter()
+                .map(|call| {
+                    model.estimate_tokens(&call.function.name)
+                        + call
+                            .function
+                            .arguments
+                            .iter()
+                            .map(|a| {
+                                model.estimate_tokens(&a.name) + model.estimate_tokens(&a.value)
+                            })
+                            .sum::<u64>()
+                })
+                .sum();
+            content + calls
+        }
+    };
+    MESSAGE_OVERHEAD + tokens
+}
+
+fn content_mut(message: &mut ChatMessage) -> Option<&mut String> {
+    match message {
+        ChatMessage::System { content }
+        | ChatMessage::User { content }
+        | ChatMessage::Tool { content, .. } => Some(content),
+        ChatMessage::Assistant(assistant) => assistant.content.as_mut(),
+    }
+}
+
+/// Truncates `content` to at most `len` bytes, including the marker that marks it
+/// as truncated, respecting character boundaries. If `len` leaves no room for the
+/// marker, the content is cut without one. Returns whether it was truncated.
+pub(crate) fn truncate(content: &mut String, len: usize) -> bool {
+    if content.len() <= len {
+        return false;
+    }
+    match len.checked_sub(TRUNCATION_MARKER.len()) {
+        Some(kept) => {
+            content.truncate(content.floor_char_boundary(kept));
+            content.push_str(TRUNCATION_MARKER);
+        }
+        None => content.truncate(content.floor_char_boundary(len)),
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::AssistantMessage;
+    use crate::testing::tool_call;
+    use crate::tool::ToolBuilder;
+
+    fn system(content: &str) -> ChatMessage {
+        ChatMessage::System {
+            content: content.to_string(),
+        }
+    }
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage::User {
+            content: content.to_string(),
+        }
+    }
+
+    fn assistant(content: &str) -> ChatMessage {
+        ChatMessage::Assistant(AssistantMessage {
+            content: Some(content.to_string()),
+            tool_calls: vec![],
+        })
+    }
+
+    fn total(messages: &[ChatMessage]) -> u64 {
+        messages
+            .iter()
+            .map(|m| estimate(&Model::Llama3_1_8B, m))
+            .sum()
+    }
+
+    #[test]
+    fn keeps_messages_within_the_budget() {
+        let messages = vec![system("Be brief"), user("Hello"), assistant("Hi!")];
+        assert_eq!(
+            fit(&Model::Llama3_1_8B, messages.clone(), total(&messages)),
+            messages
+        );
+    }
+
+    #[test]
+    fn drops_the_oldest_messages_but_not_system_messages() {
+        let messages = vec![
+            system("Be brief"),
+            user("What's the capital of France?"),
+            assistant("Paris."),
+            user("And of Italy?"),
+        ];
+        let budget = total(&[
+            system("Be brief"),
+            assistant("Paris."),
+            user("And of Italy?"),
+        ]);
+
+        assert_eq!(
+            fit(&Model::Llama3_1_8B, messages, budget),
+            vec![
+                system("Be brief"),
+                assistant("Paris."),
+                user("And of Italy?")
+            ]
+        );
+    }
+
+    #[test]
+    fn never_orphans_tool_messages() {
+        let calls = ChatMessage::Assistant(AssistantMessage {
+            content: None,
+            tool_calls: vec![
+                tool_call("call_1", "get_weather", &[("city", "Paris")]),
+                tool_call("call_2", "get_weather", &[("city", "Rome")]),
+            ],
+        });
+        let result = |id: &str| ChatMessage::Tool {
+            content: "Sunny".to_string(),
+            tool_call_id: id.to_string(),
+        };
+        let messages = vec![
+            user("Weather in Paris and Rome?"),
+            calls,
+            result("call_1"),
+            result("call_2"),
+            assistant("Sunny in both."),
+            user("Thanks!"),
+        ];
+
+        // There is room for the last two messages and one tool message, but that
+        // would leave the tool message without its tool calls.
+        let budget = total(&messages[3..]);
+        assert_eq!(
+            fit(&Model::Llama3_1_8B, messages.clone(), budget),
+            messages[4..]
+        );
+    }
+
+    #[test]
+    fn truncates_messages_that_do_not_fit() {
+        let document = "All work and no play makes Jack a dull boy. ".repeat(100);
+        let messages = vec![system("Summarize the document"), user(&document)];
+
+        let fitted = fit(&Model::Llama3_1_8B, messages, 200);
+
+        assert_eq!(fitted[0], system("Summarize the document"));
+        let ChatMessage::User { content } = &fitted[1] else {
+            panic!("expected a user message");
+        };
+        assert!(content.ends_with(" [truncated]"));
+        assert!(document.starts_with(content.strip_suffix(" [truncated]").unwrap()));
+        assert!(total(&fitted) <= 200);
+    }
+
+    #[test]
+    fn truncation_marker_counts_towards_the_length() {
+        let mut content = "All work and no play makes Jack a dull boy.".to_string();
+        assert!(truncate(&mut content, 20));
+        assert_eq!(content, "All work [truncated]");
+
+        let mut content = "Jack".to_string();
+        assert!(!truncate(&mut content, 4));
+        assert!(truncate(&mut content, 2));
+        assert_eq!(content, "Ja");
+    }
+
+    #[test]
+    fn model_window_reserves_room_for_the_response_and_tools() {
+        let model = Model::Qwen3_32B;
+        assert_eq!(
+            ContextBudget::ModelWindow.for_messages(&model, &[], None),
+            Some(32_768 - 8_192)
+        );
+        assert_eq!(
+            ContextBudget::ModelWindow.for_messages(&model, &[], Some(1_000)),
+            Some(32_768 - 1_000)
+        );
+
+        let tools = [ToolBuilder::new("get_weather").build()];
+        let tool_tokens = model.estimate_tokens(&serde_json::to_string(&tools).unwrap());
+        assert_eq!(
+            ContextBudget::Tokens(1_000).for_messages(&model, &tools, None),
+            Some(1_000 - tool_tokens)
+        );
+        assert_eq!(
+            ContextBudget::ModelWindow.for_messages(&Model::Custom("tiny".into()), &[], None),
+            None
+        );
+    }
+}
@@ -0,0 +1,841 @@
+use crate::chat::{ChatBuilder, ChatMessage};
+use crate::context;
+use crate::error::LlmError;
+use crate::model::Model;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The instruction for summarizing the older messages of a conversation.
+const SUMMARY_INSTRUCTION: &str = "Summarize the following conversation between a user \
+    and an assistant. Keep every fact, preference, decision and open question that may \
+    matter later, and leave out small talk. Answer only with the summary.";
+
+/// The chat history of a session, with the system prompt it was started with.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Conversation {
+    pub system_prompt: Option<String>,
+    /// The messages of the conversation, in order, without the system prompt and
+    /// without the messages that were replaced by a summary.
+    pub messages: Vec<ChatMessage>,
+    pub metadata: BTreeMap<String, String>,
+    /// When to replace older messages by a summary, see [`Conversation::compact`].
+    pub compaction: Option<Compaction>,
+    /// The summaries of older messages, oldest first. Only the latest one is sent
+    /// to the model, since it covers the earlier ones.
+    pub summaries: Vec<ConversationSummary>,
+    /// When the conversation was first stored, in nanoseconds since the UNIX epoch.
+    pub created_at: u64,
+    /// When the conversation was last stored, in nanoseconds since the UNIX epoch.
+    pub updated_at: u64,
+}
+
+impl Conversation {
+    /// Creates an empty conversation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the system prompt, which precedes the messages in every request.
+    pub fn with_system_prompt<S: Into<String>>(mut self, system_prompt: S) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    /// Sets a metadata entry, e.g. a title or the model the conversation is held with.
+    pub fn with_metadata<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets when older messages are replaced by a summary.
+    pub fn with_compaction(mut self, compaction: Compaction) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Appends a message to the conversation.
+    pub fn push(&mut self, message: ChatMessage) {
+        self.messages.push(message);
+    }
+
+    /// Returns the messages to send to the model: the system prompt and the latest
+    /// summary, if any, followed by the messages of the conversation.
+    ///
+    /// The result can be passed to [`ChatBuilder::with_messages`](crate::ChatBuilder::with_messages)
+    /// or [`Agent::run`](crate::Agent::run).
+    pub fn to_messages(&self) -> Vec<ChatMessage> {
+        let summary = self
+            .summaries
+            .last()
+            .map(|summary| format!("Summary of the earlier conversation:\n{}", summary.content));
+        self.system_prompt
+            .iter()
+            .cloned()
+            .chain(summary)
+            .map(|content| ChatMessage::System { content })
+            .chain(self.messages.iter().cloned())
+            .collect()
+    }
+
+    /// Replaces the older messages by a summary written by the model, if the
+    /// conversation exceeds the token threshold of its [`Compaction`].
+    ///
+    /// The summary is requested with `chat`, which determines the model whose
+    /// tokenizer is used to estimate the size of the conversation. The most recent
+    /// messages are kept verbatim, and so are tool messages whose tool calls are
+    /// kept. Returns whether the conversation was compacted, which it isn't if the
+    /// summary is empty or was cut off at the maximum number of tokens.
+    pub async fn compact(&mut self, chat: ChatBuilder) -> Result<bool, LlmError> {
+        let Some(compaction) = self.compaction else {
+            return Ok(false);
+        };
+        let tokens: u64 = self
+            .to_messages()
+            .iter()
+            .map(|message| context::estimate(chat.model(), message))
+            .sum();
+        if tokens <= compaction.max_tokens {
+            return Ok(false);
+        }
+
+        let mut split = self
+            .messages
+            .len()
+            .saturating_sub(compaction.keep_recent as usize);
+        while split > 0
+            && split < self.messages.len()
+            && matches!(self.messages[split], ChatMessage::Tool { .. })
+        {
+            split -= 1;
+        }
+        if split == 0 {
+            return Ok(false);
+        }
+
+        let previous = self.summaries.last();
+        let transcript = previous
+            .map(|summary| format!("Summary of the earlier conversation: {}", summary.content))
+            .into_iter()
+            .chain(
+                self.messages[..split]
+                    .iter()
+                    .map(|message| transcript_line(message, chat.model())),
+            )
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let response = chat
+            .with_messages(vec![
+                ChatMessage::System {
+                    content: SUMMARY_INSTRUCTION.to_string(),
+                },
+                ChatMessage::User {
+                    content: transcript,
+                },
+            ])
+            .with_tools(vec![])
+            .with_reasoning_stripped()
+            .try_send()
+            .await?;
+        let summary = response
+            .message
+            .content
+            .as_deref()
+            .unwrap_or_default()
+            .trim();
+        if summary.is_empty() || response.is_truncated() {
+            return Ok(false);
+        }
+
+        let start = previous.map_or(0, |summary| summary.start);
+        let end = previous.map_or(0, |summary| summary.end) + split as u64;
+        self.summaries.push(ConversationSummary {
+            content: summary.to_string(),
+            start,
+            end,
+        });
+        self.messages.drain(..split);
+        Ok(true)
+    }
+}
+
+/// When to replace the older messages of a [`Conversation`] by a summary.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compaction {
+    /// The estimated number of tokens above which the conversation is compacted.
+    pub max_tokens: u64,
+    /// The number of most recent messages that are kept verbatim.
+    pub keep_recent: u32,
+}
+
+impl Compaction {
+    /// Compacts conversations above `max_tokens`, keeping the `keep_recent` most
+    /// recent messages verbatim.
+    pub fn new(max_tokens: u64, keep_recent: u32) -> Self {
+        Self {
+            max_tokens,
+            keep_recent,
+        }
+    }
+}
+
+/// A summary of messages of a [`Conversation`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConversationSummary {
+    pub content: String,
+    /// The index of the first summarized message, counting all messages ever
+    /// appended to the conversation.
+    pub start: u64,
+    /// The index after the last summarized message.
+    pub end: u64,
+}
+
+/// Renders a message for the transcript that is summarized.
+fn transcript_line(message: &ChatMessage, model: &Model) -> String {
+    match message {
+        ChatMessage::System { content } => format!("System: {content}"),
+        ChatMessage::User { content } => format!("User: {content}"),
+        ChatMessage::Tool { content, .. } => format!("Tool result: {content}"),
+        ChatMessage::Assistant(assistant) => {
+            let calls = assistant.tool_calls.iter().map(|call| {
+                let arguments: Vec<_> = call
+                    .function
+                    .arguments
+                    .iter()
+                    .map(|argument| format!("{}={}", argument.name, argument.value))
+                    .collect();
+                format!(
+                    "Assistant called {}({})",
+                    call.function.name,
+                    arguments.join(", ")
+                )
+            });
+            assistant
+                .answer_for(model)
+                .filter(|answer| !answer.is_empty())
+                .map(|answer| format!("Assistant: {answer}"))
+                .into_iter()
+                .chain(calls)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    }
+}
+
+#[cfg(feature = "stable-structures")]
+pub use store::ConversationStore;
+
+#[cfg(feature = "stable-structures")]
+mod store {
+    use super::Conversation;
+    use candid::Principal;
+    use ic_stable_structures::storable::Bound;
+    use ic_stable_structures::{Memory, StableBTreeMap, Storable};
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    impl Storable for Conversation {
+        fn to_bytes(&self) -> Cow<'_, [u8]> {
+            Cow::Owned(self.clone().into_bytes())
+        }
+
+        fn into_bytes(self) -> Vec<u8> {
+            candid::encode_one(self).expect("failed to encode conversation")
+        }
+
+        fn from_bytes(bytes: Cow<[u8]>) -> Self {
+            candid::decode_one(&bytes).expect("failed to decode conversation")
+        }
+
+        const BOUND: Bound = Bound::Unbounded;
+    }
+
+    /// The key of a conversation, ordered by caller first so that the sessions of a
+    /// caller are adjacent.
+    ///
+    /// The key includes when the conversation was last stored, so that expired
+    /// sessions can be skipped without reading their conversations.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct SessionKey {
+        caller: Principal,
+        session: String,
+        updated_at: u64,
+    }
+
+    impl SessionKey {
+        fn new(caller: Principal, session: &str, updated_at: u64) -> Self {
+            Self {
+                caller,
+                session: session.to_string(),
+                updated_at,
+            }
+        }
+
+        fn is_session(&self, caller: Principal, session: &str) -> bool {
+            self.caller == caller && self.session == session
+        }
+    }
+
+    impl Storable for SessionKey {
+        fn to_bytes(&self) -> Cow<'_, [u8]> {
+            Cow::Owned(self.clone().into_bytes())
+        }
+
+        fn into_bytes(self) -> Vec<u8> {
+            encode_key(self.caller, &self.session, self.updated_at)
+        }
+
+        fn from_bytes(bytes: Cow<[u8]>) -> Self {
+            let (caller, session, updated_at) = decode_key(&bytes);
+            Self {
+                caller,
+                session,
+                updated_at,
+            }
+        }
+
+        const BOUND: Bound = Bound::Unbounded;
+    }
+
+    /// The key of the expiry index, ordered by when the conversation was last stored
+    /// so that the expired conversations come first.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct ExpiryKey {
+        updated_at: u64,
+        caller: Principal,
+        session: String,
+    }
+
+    impl From<SessionKey> for ExpiryKey {
+        fn from(key: SessionKey) -> Self {
+            Self {
+                updated_at: key.updated_at,
+                caller: key.caller,
+                session: key.session,
+            }
+        }
+    }
+
+    impl From<ExpiryKey> for SessionKey {
+        fn from(key: ExpiryKey) -> Self {
+            Self {
+                caller: key.caller,
+                session: key.session,
+                updated_at: key.updated_at,
+            }
+        }
+    }
+
+    impl Storable for ExpiryKey {
+        fn to_bytes(&self) -> Cow<'_, [u8]> {
+            Cow::Owned(self.clone().into_bytes())
+        }
+
+        fn into_bytes(self) -> Vec<u8> {
+            encode_key(self.caller, &self.session, self.updated_at)
+        }
+
+        fn from_bytes(bytes: Cow<[u8]>) -> Self {
+            SessionKey::from_bytes(bytes).into()
+        }
+
+        const BOUND: Bound = Bound::Unbounded;
+    }
+
+    fn encode_key(caller: Principal, session: &str, updated_at: u64) -> Vec<u8> {
+        let caller = caller.as_slice();
+        let mut bytes = Vec::with_capacity(1 + caller.len() + 8 + session.len());
+        bytes.push(caller.len() as u8);
+        bytes.extend_from_slice(caller);
+        bytes.extend_from_slice(&updated_at.to_be_bytes());
+        bytes.extend_from_slice(session.as_bytes());
+        bytes
+    }
+
+    fn decode_key(bytes: &[u8]) -> (Principal, String, u64) {
+        let (caller, rest) = bytes[1..].split_at(bytes[0] as usize);
+        let (updated_at, session) = rest.split_at(8);
+        (
+            Principal::from_slice(caller),
+            String::from_utf8(session.to_vec()).expect("invalid session id"),
+            u64::from_be_bytes(updated_at.try_into().unwrap()),
+        )
+    }
+
+    /// Conversations kept in stable memory, keyed by the caller and a session id, so
+    /// that they survive canister upgrades.
+    ///
+    /// The store uses two memories: one for the conversations and one for an index
+    /// of when they were last stored.
+    ///
+    /// Conversations that weren't stored for longer than the time-to-live are
+    /// treated as absent, and are removed by
+    /// [`evict_expired`](ConversationStore::evict_expired), which can be run
+    /// periodically from a timer.
+    ///
+    /// ```
+    /// use ic_llm::{ChatMessage, Conversation, ConversationStore};
+    /// use ic_stable_structures::DefaultMemoryImpl;
+    /// use std::time::Duration;
+    ///
+    /// let mut store =
+    ///     ConversationStore::init(DefaultMemoryImpl::default(), DefaultMemoryImpl::default())
+    ///         .with_ttl(Duration::from_secs(24 * 60 * 60))
+    ///         # .with_clock(|| 0)
+    ///         ;
+    /// let caller = candid::Principal::anonymous();
+    ///
+    /// let mut conversation = store
+    ///     .get(caller, "default")
+    ///     .unwrap_or_else(|| Conversation::new().with_system_prompt("You are a helpful assistant"));
+    /// conversation.push(ChatMessage::User {
+    ///     content: "Hello".to_string(),
+    /// });
+    /// store.insert(caller, "default", conversation);
+    /// ```
+    pub struct ConversationStore<M: Memory> {
+        /// The encoded conversations, which are only decoded when they're read, not
+        /// when they're replaced or evicted.
+        conversations: StableBTreeMap<SessionKey, Vec<u8>, M>,
+        expiry: StableBTreeMap<ExpiryKey, (), M>,
+        ttl: Option<Duration>,
+        clock: fn() -> u64,
+    }
+
+    impl<M: Memory> ConversationStore<M> {
+        /// Initializes the store in the given memories, keeping the conversations that
+        /// are already stored there.
+        ///
+        /// The memories must be distinct, and must be passed in the same order on
+        /// every initialization.
+        pub fn init(conversations_memory: M, index_memory: M) -> Self {
+            Self {
+                conversations: StableBTreeMap::init(conversations_memory),
+                expiry: StableBTreeMap::init(index_memory),
+                ttl: None,
+                clock: ic_cdk::api::time,
+            }
+        }
+
+        /// Sets how long conversations are kept after they were last stored.
+        ///
+        /// By default, conversations are kept until they are removed.
+        pub fn with_ttl(mut self, ttl: Duration) -> Self {
+            self.ttl = Some(ttl);
+            self
+        }
+
+        /// Sets the function returning the current time in nanoseconds since the UNIX
+        /// epoch, e.g. to use the store outside of a canister in tests.
+        ///
+        /// Defaults to [`ic_cdk::api::time`].
+        pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        /// Returns the conversation of a caller's session, unless it has expired.
+        pub fn get(&self, caller: Principal, session: &str) -> Option<Conversation> {
+            let now = (self.clock)();
+            self.key(caller, session)
+                .filter(|key| !self.is_expired(key.updated_at, now))
+                .and_then(|key| self.conversations.get(&key))
+                .map(|bytes| Conversation::from_bytes(Cow::Owned(bytes)))
+        }
+
+        /// Stores the conversation of a caller's session, replacing the previous one.
+        ///
+        /// Sets the conversation's `updated_at` to the current time, and its
+        /// `created_at` too if the conversation was never stored.
+        pub fn insert(&mut self, caller: Principal, session: &str, mut conversation: Conversation) {
+            let now = (self.clock)();
+            if conversation.created_at == 0 {
+                conversation.created_at = now;
+            }
+            conversation.updated_at = now;
+            if let Some(old) = self.key(caller, session) {
+                self.expiry.remove(&old.clone().into());
+                self.conversations.remove(&old);
+            }
+            let key = SessionKey::new(caller, session, now);
+            self.expiry.insert(key.clone().into(), ());
+            self.conversations.insert(key, conversation.into_bytes());
+        }
+
+        /// Removes the conversation of a caller's session, returning it if it existed.
+        pub fn remove(&mut self, caller: Principal, session: &str) -> Option<Conversation> {
+            let key = self.key(caller, session)?;
+            self.expiry.remove(&key.clone().into());
+            self.conversations
+                .remove(&key)
+                .map(|bytes| Conversation::from_bytes(Cow::Owned(bytes)))
+        }
+
+        /// Returns the ids of the caller's sessions that haven't expired.
+        ///
+        /// Only the keys of the caller's sessions are read, not their conversations.
+        pub fn sessions(&self, caller: Principal) -> Vec<String> {
+            let now = (self.clock)();
+            self.conversations
+                .keys_range(SessionKey::new(caller, "", 0)..)
+                .take_while(|key| key.caller == caller)
+                .filter(|key| !self.is_expired(key.updated_at, now))
+                .map(|key| key.session)
+                .collect()
+        }
+
+        /// Removes up to `limit` expired conversations, least recently stored first,
+        /// returning how many were removed.
+        ///
+        /// Only the expired entries of the index are read, so the limit bounds the
+        /// work of each call, e.g. from a periodic timer. If it returns `limit`, more
+        /// conversations may have expired.
+        pub fn evict_expired(&mut self, limit: usize) -> usize {
+            let now = (self.clock)();
+            let expired: Vec<_> = self
+                .expiry
+                .keys()
+                .take_while(|key| self.is_expired(key.updated_at, now))
+                .take(limit)
+                .collect();
+            for key in &expired {
+                self.expiry.remove(key);
+                self.conversations.remove(&key.clone().into());
+            }
+            expired.len()
+        }
+
+        /// Returns the number of stored conversations, including expired ones that
+        /// weren't evicted yet.
+        pub fn len(&self) -> u64 {
+            self.conversations.len()
+        }
+
+        /// Returns whether no conversations are stored.
+        pub fn is_empty(&self) -> bool {
+            self.conversations.is_empty()
+        }
+
+        /// Returns the key of a caller's session, whether or not it has expired.
+        fn key(&self, caller: Principal, session: &str) -> Option<SessionKey> {
+            self.conversations
+                .keys_range(SessionKey::new(caller, session, 0)..)
+                .next()
+                .filter(|key| key.is_session(caller, session))
+        }
+
+        fn is_expired(&self, updated_at: u64, now: u64) -> bool {
+            self.ttl
+                .is_some_and(|ttl| updated_at.saturating_add(ttl.as_nanos() as u64) <= now)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::ChatMessage;
+        use ic_stable_structures::VectorMemory;
+        use std::cell::Cell;
+
+        const HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+        thread_local! {
+            static NOW: Cell<u64> = const { Cell::new(HOUR) };
+        }
+
+        fn now() -> u64 {
+            NOW.with(Cell::get)
+        }
+
+        fn advance(nanos: u64) {
+            NOW.with(|now| now.set(now.get() + nanos));
+        }
+
+        fn user(content: &str) -> ChatMessage {
+            ChatMessage::User {
+                content: content.to_string(),
+            }
+        }
+
+        #[test]
+        fn stores_conversations_per_caller_and_session() {
+            let alice = Principal::from_slice(&[1]);
+            let bob = Principal::from_slice(&[2]);
+            let mut store =
+                ConversationStore::init(VectorMemory::default(), VectorMemory::default())
+                    .with_clock(now);
+
+            let mut conversation = Conversation::new().with_system_prompt("Be brief");
+            conversation.push(user("Hello"));
+            store.insert(alice, "work", conversation.clone());
+            store.insert(alice, "home", Conversation::new());
+            store.insert(bob, "work", Conversation::new());
+
+            let stored = store.get(alice, "work").unwrap();
+            assert_eq!(stored.messages, conversation.messages);
+            assert_eq!((stored.created_at, stored.updated_at), (HOUR, HOUR));
+            assert_eq!(store.get(bob, "work").unwrap().messages, vec![]);
+            assert_eq!(store.get(bob, "home"), None);
+            assert_eq!(store.sessions(alice), vec!["home", "work"]);
+            assert_eq!(store.sessions(bob), vec!["work"]);
+
+            advance(HOUR);
+            store.insert(alice, "work", stored);
+            let stored = store.get(alice, "work").unwrap();
+            assert_eq!((stored.created_at, stored.updated_at), (HOUR, 2 * HOUR));
+
+            assert!(store.remove(alice, "work").is_some());
+            assert_eq!(store.sessions(alice), vec!["home"]);
+        }
+
+        #[test]
+        fn conversations_survive_reinitialization() {
+            let (conversations, index) = (VectorMemory::default(), VectorMemory::default());
+            let caller = Principal::anonymous();
+            let mut conversation = Conversation::new().with_metadata("title", "Greetings");
+            conversation.push(user("Hello"));
+
+            ConversationStore::init(conversations.clone(), index.clone())
+                .with_clock(now)
+                .insert(caller, "default", conversation);
+            let store = ConversationStore::init(conversations, index).with_clock(now);
+
+            let stored = store.get(caller, "default").unwrap();
+            assert_eq!(stored.metadata["title"], "Greetings");
+            assert_eq!(stored.messages, vec![user("Hello")]);
+        }
+
+        #[test]
+        fn storing_a_session_again_replaces_it_without_decoding_it() {
+            let caller = Principal::anonymous();
+            let mut store =
+                ConversationStore::init(VectorMemory::default(), VectorMemory::default())
+                    .with_clock(now);
+            store.insert(caller, "default", Conversation::new());
+            // Replacing the conversation must not decode the previous one.
+            let key = store.key(caller, "default").unwrap();
+            store
+                .conversations
+                .insert(key, b"not a conversation".to_vec());
+
+            advance(HOUR);
+            let mut conversation = Conversation::new();
+            conversation.push(user("Hello"));
+            store.insert(caller, "default", conversation);
+
+            assert_eq!(store.len(), 1);
+            assert_eq!(store.expiry.len(), 1);
+            assert_eq!(store.sessions(caller), vec!["default"]);
+            let stored = store.get(caller, "default").unwrap();
+            assert_eq!(stored.messages, vec![user("Hello")]);
+        }
+
+        #[test]
+        fn expired_conversations_are_hidden_and_evicted() {
+            let caller = Principal::anonymous();
+            let mut store =
+                ConversationStore::init(VectorMemory::default(), VectorMemory::default())
+                    .with_clock(now)
+                    .with_ttl(Duration::from_secs(60 * 60));
+            for session in ["a", "b", "c", "refreshed"] {
+                store.insert(caller, session, Conversation::new());
+            }
+            advance(HOUR / 2);
+            store.insert(caller, "new", Conversation::new());
+            let refreshed = store.get(caller, "refreshed").unwrap();
+            store.insert(caller, "refreshed", refreshed);
+            advance(HOUR / 2);
+
+            assert_eq!(store.get(caller, "a"), None);
+            assert!(store.get(caller, "new").is_some());
+            assert_eq!(store.sessions(caller), vec!["new", "refreshed"]);
+            assert_eq!(store.len(), 5);
+
+            assert_eq!(store.evict_expired(2), 2);
+            assert_eq!(store.evict_expired(2), 1);
+            assert_eq!(store.evict_expired(2), 0);
+            assert_eq!(store.len(), 2);
+            assert_eq!(store.sessions(caller), vec!["new", "refreshed"]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{AssistantMessage, FinishReason, Response};
+    use crate::testing::{block_on, text_response, tool_call, MockLlm, RequestMatcher};
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage::User {
+            content: content.to_string(),
+        }
+    }
+
+    fn assistant(content: &str) -> ChatMessage {
+        ChatMessage::Assistant(text_response(content).message)
+    }
+
+    #[test]
+    fn to_messages_starts_with_the_system_prompt() {
+        let mut conversation = Conversation::new();
+        conversation.push(ChatMessage::User {
+            content: "Hello".to_string(),
+        });
+        assert_eq!(conversation.to_messages(), conversation.messages);
+
+        let conversation = conversation.with_system_prompt("Be brief");
+        assert_eq!(
+            conversation.to_messages(),
+            vec![
+                ChatMessage::System {
+                    content: "Be brief".to_string(),
+                },
+                ChatMessage::User {
+                    content: "Hello".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_replaces_older_messages_by_a_summary() {
+        let mock = MockLlm::new();
+        let chat = ChatBuilder::new(Model::Llama3_1_8B).with_transport(mock.clone());
+        let mut conversation = Conversation::new()
+            .with_system_prompt("Be brief")
+            .with_compaction(Compaction::new(20, 4));
+        conversation.push(user("I live in Paris. What's the weather?"));
+        conversation.push(ChatMessage::Assistant(AssistantMessage {
+            content: None,
+            tool_calls: vec![tool_call("call_1", "get_weather", &[("city", "Paris")])],
+        }));
+        conversation.push(ChatMessage::Tool {
+            content: "Sunny".to_string(),
+            tool_call_id: "call_1".to_string(),
+        });
+        conversation.push(assistant("It's sunny."));
+        conversation.push(user("Thanks!"));
+
+        // Keeping the last four messages would orphan the tool message.
+        mock.reply(text_response(
+            "<think>Summarize.</think>The user lives in Paris.",
+        ));
+        assert_eq!(block_on(conversation.compact(chat.clone())), Ok(true));
+        mock.assert_request(
+            0,
+            RequestMatcher::new()
+                .with_message_containing("User: I live in Paris. What's the weather?"),
+        );
+        assert_eq!(conversation.messages.len(), 4);
+        assert_eq!(
+            conversation.summaries,
+            vec![ConversationSummary {
+                content: "The user lives in Paris.".to_string(),
+                start: 0,
+                end: 1,
+            }]
+        );
+        assert_eq!(
+            conversation.to_messages()[..3],
+            [
+                ChatMessage::System {
+                    content: "Be brief".to_string(),
+                },
+                ChatMessage::System {
+                    content: "Summary of the earlier conversation:\nThe user lives in Paris."
+                        .to_string(),
+                },
+                conversation.messages[0].clone(),
+            ]
+        );
+
+        // The next summary covers the previous one.
+        conversation.push(assistant("You're welcome!"));
+        conversation.push(user("Bye!"));
+        mock.reply(text_response("The user lives in Paris, where it's sunny."));
+        assert_eq!(block_on(conversation.compact(chat)), Ok(true));
+        mock.assert_request(
+            1,
+            RequestMatcher::new()
+                .with_message_containing(
+                    "Summary of the earlier conversation: The user lives in Paris.",
+                )
+                .with_message_containing("Assistant called get_weather(city=Paris)")
+                .with_message_containing("Tool result: Sunny"),
+        );
+        assert_eq!(
+            conversation.messages,
+            vec![
+                assistant("It's sunny."),
+                user("Thanks!"),
+                assistant("You're welcome!"),
+                user("Bye!"),
+            ]
+        );
+        assert_eq!(
+            (
+                conversation.summaries[1].start,
+                conversation.summaries[1].end
+            ),
+            (0, 3)
+        );
+        mock.assert_done();
+    }
+
+    #[test]
+    fn compact_keeps_conversations_within_the_threshold() {
+        let mock = MockLlm::new();
+        let chat = ChatBuilder::new(Model::Llama3_1_8B).with_transport(mock.clone());
+        let mut conversation = Conversation::new();
+        conversation.push(user("Hello"));
+        conversation.push(assistant("Hi!"));
+
+        assert_eq!(block_on(conversation.compact(chat.clone())), Ok(false));
+        let mut conversation = conversation.with_compaction(Compaction::new(1_000, 1));
+        assert_eq!(block_on(conversation.compact(chat)), Ok(false));
+        assert_eq!(conversation.messages.len(), 2);
+        assert!(mock.requests().is_empty());
+    }
+
+    #[test]
+    fn compact_keeps_messages_without_a_usable_summary() {
+        let mock = MockLlm::new();
+        let chat = ChatBuilder::new(Model::Llama3_1_8B).with_transport(mock.clone());
+        let mut conversation = Conversation::new().with_compaction(Compaction::new(1, 1));
+        conversation.push(user("I live in Paris."));
+        conversation.push(assistant("Noted."));
+
+        mock.reply(text_response("<think>Nothing to say.</think>"));
+        assert_eq!(block_on(conversation.compact(chat.clone())), Ok(false));
+        mock.reply(Response {
+            finish_reason: Some(FinishReason::Length),
+            ..text_response("The user lives")
+        });
+        assert_eq!(block_on(conversation.compact(chat)), Ok(false));
+
+        assert_eq!(conversation.messages.len(), 2);
+        assert!(conversation.summaries.is_empty());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn compact_can_summarize_all_messages() {
+        let mock = MockLlm::new();
+        let chat = ChatBuilder::new(Model::Llama3_1_8B).with_transport(mock.clone());
+        let mut conversation = Conversation::new().with_compaction(Compaction::new(1, 0));
+        conversation.push(user("I live in Paris."));
+        conversation.push(assistant("Noted."));
+
+        mock.reply(text_response("The user lives in Paris."));
+        assert_eq!(block_on(conversation.compact(chat)), Ok(true));
+        assert!(conversation.messages.is_empty());
+        assert_eq!(
+            (
+                conversation.summaries[0].start,
+                conversation.summaries[0].end
+            ),
+            (0, 2)
+        );
+    }
+}
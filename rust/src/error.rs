@@ -0,0 +1,270 @@
+use crate::retry::RetryPolicy;
+use crate::tool::ToolChoice;
+use ic_cdk::call::{CallFailed, RejectCode};
+use std::fmt;
+
+/// The maximum size in bytes of an inter-canister request payload.
+pub(crate) const MAX_REQUEST_BYTES: usize = 2 * 1024 * 1024;
+
+/// The size of a message of a request, see [`LlmError::RequestTooLarge`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageSize {
+    /// The index of the message in the request.
+    pub index: usize,
+    /// The number of bytes of the texts of the message, which make up almost all
+    /// of its encoded size.
+    pub bytes: usize,
+}
+
+/// Errors that can occur when sending a request to the LLM canister.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LlmError {
+    /// The call was rejected with `SYS_TRANSIENT`. Retrying later may succeed.
+    Transient(String),
+    /// The LLM canister does not exist or does not expose the chat endpoint.
+    DestinationInvalid(String),
+    /// The LLM canister rejected the request or trapped while handling it.
+    CanisterError(String),
+    /// No response was received in time, or it was lost. Unlike the other errors,
+    /// this doesn't mean that the request failed: the LLM canister may have
+    /// processed it, or may still do so.
+    Timeout(String),
+    /// The call could not be performed (e.g. insufficient cycles or a fatal system error).
+    CallFailed(String),
+    /// The request could not be encoded.
+    Encode(String),
+    /// The response of the LLM canister could not be decoded.
+    Decode(String),
+    /// The encoded request exceeds the inter-canister message size limit.
+    RequestTooLarge {
+        size: usize,
+        limit: usize,
+        /// The largest messages of the request, largest first.
+        largest_messages: Vec<MessageSize>,
+    },
+    /// Every attempt allowed by the retry policy failed with a retryable error.
+    RetriesExhausted {
+        attempts: u32,
+        policy: RetryPolicy,
+        last_error: Box<LlmError>,
+    },
+    /// The model didn't comply with the requested [`ToolChoice`], even after being
+    /// reminded of it.
+    ToolChoiceIgnored(ToolChoice),
+    /// The [`ToolChoice`] requires a call to a tool that isn't offered with the
+    /// request, so the request wasn't sent.
+    ToolChoiceUnsatisfiable(ToolChoice),
+    /// The model didn't answer with JSON matching the requested output, even after
+    /// being asked to correct its answer.
+    InvalidOutput { attempts: u32, error: String },
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LlmError::Transient(msg) => write!(f, "LLM call failed transiently: {msg}"),
+            LlmError::DestinationInvalid(msg) => write!(f, "invalid LLM canister: {msg}"),
+            LlmError::CanisterError(msg) => write!(f, "LLM canister error: {msg}"),
+            LlmError::Timeout(msg) => write!(f, "LLM call timed out: {msg}"),
+            LlmError::CallFailed(msg) => write!(f, "LLM call failed: {msg}"),
+            LlmError::Encode(msg) => write!(f, "failed to encode LLM request: {msg}"),
+            LlmError::Decode(msg) => write!(f, "failed to decode LLM response: {msg}"),
+            LlmError::RequestTooLarge {
+                size,
+                limit,
+                largest_messages,
+            } => {
+                write!(
+                    f,
+                    "LLM request is {size} bytes, which exceeds the limit of {limit} bytes"
+                )?;
+                if !largest_messages.is_empty() {
+                    let messages: Vec<_> = largest_messages
+                        .iter()
+                        .map(|m| format!("#{} ({} bytes)", m.index, m.bytes))
+                        .collect();
+                    write!(f, "; largest messages: {}", messages.join(", "))?;
+                }
+                Ok(())
+            }
+            LlmError::RetriesExhausted {
+                attempts,
+                last_error,
+                ..
+            } => write!(f, "LLM call failed after {attempts} attempts: {last_error}"),
+            LlmError::ToolChoiceIgnored(choice) => {
+                write!(f, "the model ignored the tool choice {choice:?}")
+            }
+            LlmError::ToolChoiceUnsatisfiable(choice) => {
+                write!(
+                    f,
+                    "the tool choice {choice:?} requires a tool that isn't offered"
+                )
+            }
+            LlmError::InvalidOutput { attempts, error } => write!(
+                f,
+                "the model didn't produce valid output in {attempts} attempts: {error}"
+            ),
+        }
+    }
+}
+
+impl LlmError {
+    /// Returns whether it is unknown if the LLM canister processed the request, i.e.
+    /// whether the (last) attempt timed out rather than being definitely rejected.
+    pub fn is_outcome_unknown(&self) -> bool {
+        match self {
+            LlmError::Timeout(_) => true,
+            LlmError::RetriesExhausted { last_error, .. } => last_error.is_outcome_unknown(),
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for LlmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LlmError::RetriesExhausted { last_error, .. } => Some(last_error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<CallFailed> for LlmError {
+    fn from(error: CallFailed) -> Self {
+        match error {
+            CallFailed::CallRejected(rejected) => {
+                let message = rejected.reject_message().to_string();
+                match rejected.reject_code() {
+                    Ok(RejectCode::SysTransient) => LlmError::Transient(message),
+                    Ok(RejectCode::DestinationInvalid) => LlmError::DestinationInvalid(message),
+                    Ok(RejectCode::CanisterReject | RejectCode::CanisterError) => {
+                        LlmError::CanisterError(message)
+                    }
+                    Ok(RejectCode::SysUnknown) => LlmError::Timeout(message),
+                    Ok(RejectCode::SysFatal) | Err(_) => LlmError::CallFailed(format!(
+                        "rejected with code {}: {message}",
+                        rejected.raw_reject_code()
+                    )),
+                }
+            }
+            other => LlmError::CallFailed(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_cdk::call::{CallPerformFailed, CallRejected};
+
+    fn rejected(code: RejectCode, message: &str) -> CallFailed {
+        CallFailed::CallRejected(CallRejected::with_rejection(
+            code as u32,
+            message.to_string(),
+        ))
+    }
+
+    #[test]
+    fn reject_codes_are_classified() {
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::SysTransient, "busy")),
+            LlmError::Transient("busy".to_string())
+        );
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::DestinationInvalid, "no such canister")),
+            LlmError::DestinationInvalid("no such canister".to_string())
+        );
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::CanisterReject, "rejected")),
+            LlmError::CanisterError("rejected".to_string())
+        );
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::CanisterError, "trapped")),
+            LlmError::CanisterError("trapped".to_string())
+        );
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::SysUnknown, "deadline expired")),
+            LlmError::Timeout("deadline expired".to_string())
+        );
+    }
+
+    #[test]
+    fn other_failures_are_call_failures() {
+        assert_eq!(
+            LlmError::from(rejected(RejectCode::SysFatal, "fatal")),
+            LlmError::CallFailed("rejected with code 1: fatal".to_string())
+        );
+        assert_eq!(
+            LlmError::from(CallFailed::CallRejected(CallRejected::with_rejection(
+                42,
+                "unknown".to_string()
+            ))),
+            LlmError::CallFailed("rejected with code 42: unknown".to_string())
+        );
+        assert_eq!(
+            LlmError::from(CallFailed::CallPerformFailed(CallPerformFailed)),
+            LlmError::CallFailed("call perform failed".to_string())
+        );
+    }
+
+    #[test]
+    fn only_timeouts_have_an_unknown_outcome() {
+        let timeout = LlmError::from(rejected(RejectCode::SysUnknown, "deadline expired"));
+        assert!(timeout.is_outcome_unknown());
+        assert!(
+            !LlmError::from(rejected(RejectCode::CanisterReject, "rejected")).is_outcome_unknown()
+        );
+        assert!(!LlmError::from(rejected(RejectCode::SysTransient, "busy")).is_outcome_unknown());
+        assert!(LlmError::RetriesExhausted {
+            attempts: 3,
+            policy: RetryPolicy::default(),
+            last_error: Box::new(timeout),
+        }
+        .is_outcome_unknown());
+    }
+
+    #[test]
+    fn encode_errors_are_not_retryable() {
+        let error = LlmError::Encode("unsupported value".to_string());
+        assert_eq!(
+            error.to_string(),
+            "failed to encode LLM request: unsupported value"
+        );
+        assert!(!RetryPolicy::default().is_retryable(&error));
+        assert!(!error.is_outcome_unknown());
+    }
+
+    #[test]
+    fn request_too_large_display() {
+        let error = LlmError::RequestTooLarge {
+            size: 3_000_000,
+            limit: MAX_REQUEST_BYTES,
+            largest_messages: vec![],
+        };
+        assert_eq!(
+            error.to_string(),
+            "LLM request is 3000000 bytes, which exceeds the limit of 2097152 bytes"
+        );
+
+        let error = LlmError::RequestTooLarge {
+            size: 3_000_000,
+            limit: MAX_REQUEST_BYTES,
+            largest_messages: vec![
+                MessageSize {
+                    index: 3,
+                    bytes: 2_900_000,
+                },
+                MessageSize {
+                    index: 0,
+                    bytes: 90_000,
+                },
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "LLM request is 3000000 bytes, which exceeds the limit of 2097152 bytes; \
+             largest messages: #3 (2900000 bytes), #0 (90000 bytes)"
+        );
+    }
+}
@@ -0,0 +1,790 @@
+use crate::tool::{Bound, Function, Items, Parameters, Property, Tool};
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+/// An error returned by [`Tool::from_json_schema`], or when deriving parameters from
+/// a type with the `schemars` feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The document isn't a valid tool definition.
+    Invalid { path: String, message: String },
+    /// The document uses keywords that a [`Tool`] cannot represent. Each entry is
+    /// the path of an unsupported keyword, e.g. `/function/parameters/properties/id/format`.
+    UnsupportedKeywords(Vec<String>),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Invalid { path, message } => {
+                write!(f, "invalid tool definition at `{path}`: {message}")
+            }
+            SchemaError::UnsupportedKeywords(paths) => {
+                write!(f, "unsupported keywords: {}", paths.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl Tool {
+    /// Returns the tool in the JSON Schema shape used by OpenAI-compatible APIs:
+    /// `{"type": "function", "function": {"name": ..., "parameters": {...}}}`.
+    ///
+    /// Unlike the wire format of the LLM canister, the properties of an object are
+    /// a map from names to schemas, and nullable properties have a `["<type>", "null"]`
+    /// type. The properties are sorted by name unless the `preserve-order` feature
+    /// is enabled.
+    pub fn to_json_schema(&self) -> Value {
+        let Tool::Function(function) = self;
+        let mut object = Map::new();
+        object.insert("name".to_string(), json!(function.name));
+        if let Some(description) = &function.description {
+            object.insert("description".to_string(), json!(description));
+        }
+        if let Some(parameters) = &function.parameters {
+            object.insert("parameters".to_string(), parameters_schema(parameters));
+        }
+        json!({ "type": "function", "function": object })
+    }
+
+    /// Builds a tool from a document in the shape produced by [`Tool::to_json_schema`].
+    ///
+    /// Keywords that cannot be represented, such as `format` or `additionalProperties`,
+    /// are reported all at once with [`SchemaError::UnsupportedKeywords`] rather than
+    /// silently dropped.
+    ///
+    /// A [`Value`] only keeps the order of the properties of an object if the
+    /// `preserve_order` feature of `serde_json` is enabled, e.g. with the
+    /// `preserve-order` feature of this crate. Otherwise, the properties of the tool
+    /// are sorted by name.
+    pub fn from_json_schema(schema: &Value) -> Result<Tool, SchemaError> {
+        let mut importer = Importer::default();
+        let tool = importer.tool(schema)?;
+        if importer.unsupported.is_empty() {
+            Ok(tool)
+        } else {
+            Err(SchemaError::UnsupportedKeywords(importer.unsupported))
+        }
+    }
+}
+
+fn object_schema(
+    type_: &str,
+    properties: Option<&[Property]>,
+    required: Option<&[String]>,
+) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_string(), json!(type_));
+    if let Some(properties) = properties {
+        let properties = properties
+            .iter()
+            .map(|property| (property.name.clone(), property_schema(property)))
+            .collect();
+        object.insert("properties".to_string(), Value::Object(properties));
+    }
+    if let Some(required) = required {
+        object.insert("required".to_string(), json!(required));
+    }
+    Value::Object(object)
+}
+
+fn items_schema(items: &Items) -> Value {
+    let mut schema = object_schema(
+        &items.type_,
+        items.properties.as_deref(),
+        items.required.as_deref(),
+    );
+    if let Some(nested) = &items.items {
+        schema["items"] = items_schema(nested);
+    }
+    schema
+}
+
+fn property_schema(property: &Property) -> Value {
+    let mut schema = object_schema(
+        &property.type_,
+        property.properties.as_deref(),
+        property.required.as_deref(),
+    );
+    let object = schema.as_object_mut().expect("object schemas are objects");
+    if property.nullable == Some(true) {
+        object.insert("type".to_string(), json!([property.type_, "null"]));
+    }
+    if let Some(description) = &property.description {
+        object.insert("description".to_string(), json!(description));
+    }
+    if let Some(enum_) = &property.enum_ {
+        object.insert("enum".to_string(), json!(enum_));
+    }
+    if let Some(items) = &property.items {
+        object.insert("items".to_string(), items_schema(items));
+    }
+    if let Some(minimum) = property.minimum {
+        object.insert("minimum".to_string(), number(minimum.0));
+    }
+    if let Some(maximum) = property.maximum {
+        object.insert("maximum".to_string(), number(maximum.0));
+    }
+    if let Some(min_length) = property.min_length {
+        object.insert("minLength".to_string(), json!(min_length));
+    }
+    if let Some(max_length) = property.max_length {
+        object.insert("maxLength".to_string(), json!(max_length));
+    }
+    if let Some(pattern) = &property.pattern {
+        object.insert("pattern".to_string(), json!(pattern));
+    }
+    if let Some(default) = &property.default {
+        // Defaults are stored in the same form as argument values, so anything
+        // but a string is JSON.
+        let default = match property.type_.as_str() {
+            "string" => json!(default),
+            _ => serde_json::from_str(default).unwrap_or_else(|_| json!(default)),
+        };
+        object.insert("default".to_string(), default);
+    }
+    schema
+}
+
+/// Converts `value` to a JSON number, without a fractional part if it has none.
+fn number(value: f64) -> Value {
+    const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+    if value.fract() == 0.0 && value.abs() <= MAX_SAFE_INTEGER {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
+}
+
+/// Returns parameters in the JSON Schema shape of [`Tool::to_json_schema`].
+pub(crate) fn parameters_schema(parameters: &Parameters) -> Value {
+    object_schema(
+        &parameters.type_,
+        parameters.properties.as_deref(),
+        parameters.required.as_deref(),
+    )
+}
+
+/// Builds parameters from the JSON schema that `schemars` generates for `T`.
+///
+/// Keywords that parameters cannot represent, such as `format`, are dropped.
+#[cfg(feature = "schemars")]
+pub(crate) fn parameters_from<T: schemars::JsonSchema>() -> Result<Parameters, SchemaError> {
+    let mut schema: Value = schemars::generate::SchemaSettings::draft07()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<T>()
+        .into();
+    normalize(&mut schema);
+    let object = as_object(&schema, "")?;
+    if object.get("type") != Some(&json!("object")) {
+        return Err(invalid("/type", "expected \"object\""));
+    }
+    let mut importer = Importer::default();
+    Ok(Parameters {
+        type_: "object".to_string(),
+        properties: importer.properties(object, "")?,
+        required: strings(object, "required", "")?,
+    })
+}
+
+/// Rewrites the constructs `schemars` uses for options and enums into the plain
+/// `type` and `enum` keywords supported by [`Property`].
+#[cfg(feature = "schemars")]
+fn normalize(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+
+    for key in ["anyOf", "oneOf"] {
+        let Some(Value::Array(variants)) = object.remove(key) else {
+            continue;
+        };
+        let variants: Vec<_> = variants
+            .into_iter()
+            .filter(|variant| variant.get("type") != Some(&json!("null")))
+            .collect();
+        if !variants.is_empty()
+            && variants
+                .iter()
+                .all(|variant| variant.get("const").is_some())
+        {
+            // Unit enums with documented variants.
+            let type_ = variants[0].get("type").cloned().unwrap_or(json!("string"));
+            let values = variants
+                .iter()
+                .filter_map(|variant| variant.get("const").cloned());
+            object.entry("type").or_insert(type_);
+            object.insert("enum".to_string(), values.collect());
+        } else if let [Value::Object(variant)] = &variants[..] {
+            // `Option<T>` of a type without a `type` keyword of its own.
+            for (key, value) in variant {
+                object.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        } else {
+            object.insert(key.to_string(), Value::Array(variants));
+        }
+    }
+
+    // `Option` fields are not required, so they don't need to be nullable.
+    if let Some(Value::Array(types)) = object.get_mut("type") {
+        types.retain(|type_| !type_.is_null() && type_ != "null");
+        if let [type_] = &types[..] {
+            let type_ = type_.clone();
+            object.insert("type".to_string(), type_);
+        }
+    }
+    if let Some(Value::Array(values)) = object.get_mut("enum") {
+        values.retain(|value| !value.is_null());
+    }
+
+    if let Some(Value::Object(properties)) = object.get_mut("properties") {
+        properties.values_mut().for_each(normalize);
+    }
+    if let Some(items) = object.get_mut("items") {
+        normalize(items);
+    }
+}
+
+/// Builds tools from JSON schemas, collecting unsupported keywords along the way.
+#[derive(Default)]
+struct Importer {
+    unsupported: Vec<String>,
+}
+
+impl Importer {
+    fn tool(&mut self, schema: &Value) -> Result<Tool, SchemaError> {
+        let object = as_object(schema, "")?;
+        self.check_keywords(object, "", &["type", "function"]);
+        match object.get("type") {
+            Some(Value::String(type_)) if type_ == "function" => {}
+            _ => return Err(invalid("/type", "expected \"function\"")),
+        }
+        let function = object
+            .get("function")
+            .ok_or_else(|| invalid("/function", "missing"))?;
+        Ok(Tool::Function(self.function(function, "/function")?))
+    }
+
+    fn function(&mut self, schema: &Value, path: &str) -> Result<Function, SchemaError> {
+        let object = as_object(schema, path)?;
+        self.check_keywords(object, path, &["name", "description", "parameters"]);
+        let parameters = match object.get("parameters") {
+            Some(parameters) => {
+                let path = format!("{path}/parameters");
+                let object = as_object(parameters, &path)?;
+                self.check_keywords(object, &path, &["type", "properties", "required"]);
+                let type_ = string(object, "type", &path)?.unwrap_or_else(|| "object".to_string());
+                if type_ != "object" {
+                    return Err(invalid(&format!("{path}/type"), "expected \"object\""));
+                }
+                Some(Parameters {
+                    type_,
+                    properties: self.properties(object, &path)?,
+                    required: strings(object, "required", &path)?,
+                })
+            }
+            None => None,
+        };
+        Ok(Function {
+            name: string(object, "name", path)?
+                .ok_or_else(|| invalid(&format!("{path}/name"), "missing"))?,
+            description: string(object, "description", path)?,
+            parameters,
+        })
+    }
+
+    fn properties(
+        &mut self,
+        object: &Map<String, Value>,
+        path: &str,
+    ) -> Result<Option<Vec<Property>>, SchemaError> {
+        let Some(properties) = object.get("properties") else {
+            return Ok(None);
+        };
+        let path = format!("{path}/properties");
+        as_object(properties, &path)?
+            .iter()
+            .map(|(name, schema)| self.property(name, schema, &format!("{path}/{name}")))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    fn property(
+        &mut self,
+        name: &str,
+        schema: &Value,
+        path: &str,
+    ) -> Result<Property, SchemaError> {
+        let object = as_object(schema, path)?;
+        self.check_keywords(
+            object,
+            path,
+            &[
+                "type",
+                "description",
+                "enum",
+                "items",
+                "properties",
+                "required",
+                "minimum",
+                "maximum",
+                "minLength",
+                "maxLength",
+                "pattern",
+                "default",
+                "nullable",
+            ],
+        );
+        let (type_, nullable_type) = type_(object, path)?;
+        let nullable = match object.get("nullable") {
+            None => nullable_type,
+            Some(Value::Bool(nullable)) => *nullable || nullable_type,
+            Some(_) => return Err(invalid(&format!("{path}/nullable"), "expected a boolean")),
+        };
+        let enum_ = match object.get("enum") {
+            None => None,
+            Some(Value::Array(values)) => Some(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(s) => s.clone(),
+                        value => value.to_string(),
+                    })
+                    .collect(),
+            ),
+            Some(_) => return Err(invalid(&format!("{path}/enum"), "expected an array")),
+        };
+        let default = object.get("default").map(|value| match value {
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        });
+        Ok(Property {
+            type_,
+            name: name.to_string(),
+            description: string(object, "description", path)?,
+            enum_,
+            items: self.items(object, path)?,
+            properties: self.properties(object, path)?,
+            required: strings(object, "required", path)?,
+            minimum: float(object, "minimum", path)?.map(Bound),
+            maximum: float(object, "maximum", path)?.map(Bound),
+            min_length: unsigned(object, "minLength", path)?,
+            max_length: unsigned(object, "maxLength", path)?,
+            pattern: pattern(object, path)?,
+            default,
+            nullable: nullable.then_some(true),
+        })
+    }
+
+    fn items(
+        &mut self,
+        object: &Map<String, Value>,
+        path: &str,
+    ) -> Result<Option<Box<Items>>, SchemaError> {
+        let Some(items) = object.get("items") else {
+            return Ok(None);
+        };
+        let path = format!("{path}/items");
+        let object = as_object(items, &path)?;
+        self.check_keywords(object, &path, &["type", "items", "properties", "required"]);
+        let (type_, nullable) = type_(object, &path)?;
+        if nullable {
+            self.unsupported.push(format!("{path}/type"));
+        }
+        Ok(Some(Box::new(Items {
+            type_,
+            items: self.items(object, &path)?,
+            properties: self.properties(object, &path)?,
+            required: strings(object, "required", &path)?,
+        })))
+    }
+
+    fn check_keywords(&mut self, object: &Map<String, Value>, path: &str, supported: &[&str]) {
+        self.unsupported.extend(
+            object
+                .keys()
+                .filter(|key| !supported.contains(&key.as_str()))
+                .map(|key| format!("{path}/{key}")),
+        );
+    }
+}
+
+fn invalid(path: &str, message: &str) -> SchemaError {
+    SchemaError::Invalid {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn as_object<'a>(value: &'a Value, path: &str) -> Result<&'a Map<String, Value>, SchemaError> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid(path, "expected an object"))
+}
+
+/// Returns the type of a schema, and whether `null` is one of its types.
+fn type_(object: &Map<String, Value>, path: &str) -> Result<(String, bool), SchemaError> {
+    let path = format!("{path}/type");
+    let types: Vec<&str> = match object.get("type") {
+        Some(Value::String(type_)) => vec![type_.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        Some(_) => return Err(invalid(&path, "expected a string or an array")),
+        None => return Err(invalid(&path, "missing")),
+    };
+    let nullable = types.contains(&"null");
+    match types
+        .into_iter()
+        .filter(|t| *t != "null")
+        .collect::<Vec<_>>()[..]
+    {
+        [type_] => Ok((type_.to_string(), nullable)),
+        _ => Err(invalid(
+            &path,
+            "expected a single type, optionally with \"null\"",
+        )),
+    }
+}
+
+fn string(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<String>, SchemaError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(invalid(&format!("{path}/{key}"), "expected a string")),
+    }
+}
+
+/// Returns the `pattern` of a schema, which must be a valid regular expression.
+fn pattern(object: &Map<String, Value>, path: &str) -> Result<Option<String>, SchemaError> {
+    let pattern = string(object, "pattern", path)?;
+    if let Some(Err(error)) = pattern.as_deref().map(regex_lite::Regex::new) {
+        return Err(invalid(
+            &format!("{path}/pattern"),
+            &format!("invalid regular expression: {error}"),
+        ));
+    }
+    Ok(pattern)
+}
+
+fn strings(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<Vec<String>>, SchemaError> {
+    let Some(value) = object.get(key) else {
+        return Ok(None);
+    };
+    value
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .map(Some)
+        .ok_or_else(|| invalid(&format!("{path}/{key}"), "expected an array of strings"))
+}
+
+fn float(object: &Map<String, Value>, key: &str, path: &str) -> Result<Option<f64>, SchemaError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{path}/{key}"), "expected a number")),
+    }
+}
+
+fn unsigned(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<u64>, SchemaError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{path}/{key}"), "expected a non-negative integer")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::{ParameterBuilder, ParameterType, ToolBuilder};
+
+    fn lookup_tool() -> Tool {
+        ToolBuilder::new("lookup_account")
+            .with_description("Lookup an ICP account")
+            .with_parameter(
+                ParameterBuilder::new("account", ParameterType::String)
+                    .with_description("The account to look up")
+                    .with_min_length(64)
+                    .with_max_length(64)
+                    .with_pattern("^[0-9a-f]*$")
+                    .is_required(),
+            )
+            .with_parameter(
+                ParameterBuilder::new("decimals", ParameterType::Integer)
+                    .with_minimum(0.0)
+                    .with_maximum(8.5)
+                    .with_default("2"),
+            )
+            .with_parameter(ParameterBuilder::new(
+                "filters",
+                ParameterType::array_of(ParameterType::Object(vec![ParameterBuilder::new(
+                    "memo",
+                    ParameterType::Integer,
+                )
+                .is_required()])),
+            ))
+            .with_parameter(
+                ParameterBuilder::new("unit", ParameterType::String)
+                    .with_enum_values(["icp", "e8s"])
+                    .is_nullable(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn exports_openai_shape() {
+        assert_eq!(
+            lookup_tool().to_json_schema(),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "lookup_account",
+                    "description": "Lookup an ICP account",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "account": {
+                                "type": "string",
+                                "description": "The account to look up",
+                                "minLength": 64,
+                                "maxLength": 64,
+                                "pattern": "^[0-9a-f]*$",
+                            },
+                            "decimals": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "maximum": 8.5,
+                                "default": 2,
+                            },
+                            "unit": {
+                                "type": ["string", "null"],
+                                "enum": ["icp", "e8s"],
+                            },
+                            "filters": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": { "memo": { "type": "integer" } },
+                                    "required": ["memo"],
+                                },
+                            },
+                        },
+                        "required": ["account"],
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn round_trips_tools() {
+        let tool = lookup_tool();
+        assert_eq!(Tool::from_json_schema(&tool.to_json_schema()), Ok(tool));
+
+        let tool = ToolBuilder::new("get_time").build();
+        assert_eq!(Tool::from_json_schema(&tool.to_json_schema()), Ok(tool));
+    }
+
+    #[test]
+    #[cfg(feature = "preserve-order")]
+    fn keeps_the_order_of_properties() {
+        let tool = ToolBuilder::new("convert")
+            .with_parameter(ParameterBuilder::new("value", ParameterType::Number))
+            .with_parameter(ParameterBuilder::new("unit", ParameterType::String))
+            .build();
+        let schema = tool.to_json_schema();
+        let properties = schema["function"]["parameters"]["properties"]
+            .as_object()
+            .unwrap();
+        assert_eq!(properties.keys().collect::<Vec<_>>(), ["value", "unit"]);
+        assert_eq!(Tool::from_json_schema(&schema), Ok(tool));
+    }
+
+    #[test]
+    fn imports_nullable_keyword() {
+        let tool = Tool::from_json_schema(&json!({
+            "type": "function",
+            "function": {
+                "name": "f",
+                "parameters": {
+                    "type": "object",
+                    "properties": { "memo": { "type": "string", "nullable": true } },
+                },
+            },
+        }))
+        .unwrap();
+
+        let Tool::Function(function) = tool;
+        let properties = function.parameters.unwrap().properties.unwrap();
+        assert_eq!(properties[0].nullable, Some(true));
+    }
+
+    #[test]
+    fn reports_unsupported_keywords() {
+        let error = Tool::from_json_schema(&json!({
+            "type": "function",
+            "function": {
+                "name": "f",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "id": { "type": "string", "format": "uuid" },
+                        "tags": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+                    },
+                },
+            },
+        }))
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            SchemaError::UnsupportedKeywords(vec![
+                "/function/strict".to_string(),
+                "/function/parameters/additionalProperties".to_string(),
+                "/function/parameters/properties/id/format".to_string(),
+                "/function/parameters/properties/tags/items/minLength".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_invalid_documents() {
+        assert_eq!(
+            Tool::from_json_schema(&json!({ "type": "function", "function": {} })),
+            Err(invalid("/function/name", "missing"))
+        );
+        assert_eq!(
+            Tool::from_json_schema(&json!({
+                "type": "function",
+                "function": {
+                    "name": "f",
+                    "parameters": {
+                        "type": "object",
+                        "properties": { "n": { "type": ["integer", "string"] } },
+                    },
+                },
+            }))
+            .unwrap_err()
+            .to_string(),
+            "invalid tool definition at `/function/parameters/properties/n/type`: \
+             expected a single type, optionally with \"null\""
+        );
+        assert!(matches!(
+            Tool::from_json_schema(&json!({
+                "type": "function",
+                "function": {
+                    "name": "f",
+                    "parameters": {
+                        "type": "object",
+                        "properties": { "id": { "type": "string", "pattern": "[0-9a-f" } },
+                    },
+                },
+            })),
+            Err(SchemaError::Invalid { path, .. }) if path == "/function/parameters/properties/id/pattern"
+        ));
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn rejects_parameters_from_non_objects() {
+        assert_eq!(
+            ToolBuilder::new("count")
+                .with_parameters_from::<u32>()
+                .err(),
+            Some(SchemaError::Invalid {
+                path: "/type".to_string(),
+                message: "expected \"object\"".to_string(),
+            })
+        );
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn derives_parameters_from_json_schema() {
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Range {
+            from: u64,
+            to: Option<u64>,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[serde(rename_all = "snake_case")]
+        #[allow(dead_code)]
+        enum Unit {
+            /// Whole tokens.
+            Icp,
+            /// Hundred-millionths of a token.
+            E8s,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Args {
+            /// The account to look up.
+            #[schemars(length(min = 64, max = 64))]
+            account: String,
+            limit: Option<Range>,
+            ranges: Vec<Range>,
+            unit: Option<Unit>,
+        }
+
+        let tool = ToolBuilder::new("lookup_account")
+            .with_parameters_from::<Args>()
+            .unwrap()
+            .with_parameter(ParameterBuilder::new("verbose", ParameterType::Boolean).is_required())
+            .build();
+
+        let range = ParameterType::Object(vec![
+            ParameterBuilder::new("from", ParameterType::Integer)
+                .with_minimum(0.0)
+                .is_required(),
+            ParameterBuilder::new("to", ParameterType::Integer).with_minimum(0.0),
+        ]);
+        let expected = ToolBuilder::new("lookup_account")
+            .with_parameter(
+                ParameterBuilder::new("account", ParameterType::String)
+                    .with_description("The account to look up.")
+                    .with_min_length(64)
+                    .with_max_length(64)
+                    .is_required(),
+            )
+            .with_parameter(ParameterBuilder::new("limit", range.clone()))
+            .with_parameter(
+                ParameterBuilder::new("ranges", ParameterType::array_of(range)).is_required(),
+            )
+            .with_parameter(
+                ParameterBuilder::new("unit", ParameterType::String)
+                    .with_enum_values(["icp", "e8s"]),
+            )
+            .with_parameter(ParameterBuilder::new("verbose", ParameterType::Boolean).is_required())
+            .build();
+
+        assert_eq!(tool, expected);
+    }
+}
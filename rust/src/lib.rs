@@ -1,16 +1,92 @@
 #![doc = include_str!("../README.md")]
 use candid::Principal;
-use std::fmt;
 
 // Define our modules
+mod agent;
+mod arguments;
 mod chat;
+mod context;
+mod conversation;
+mod error;
+mod json_schema;
+mod model;
+mod registry;
+mod retry;
+mod size;
+mod structured;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod tool;
+mod transport;
+mod validation;
 
 // Re-export public types from modules
-pub use chat::{AssistantMessage, ChatBuilder, ChatMessage, FunctionCall, Response, ToolCall};
+pub use agent::{Agent, AgentResponse};
+pub use arguments::ArgumentError;
+pub use chat::{
+    AssistantMessage, ChatBuilder, ChatMessage, FinishReason, FunctionCall, Request, Response,
+    ToolCall, ToolCallArgument, Usage,
+};
+pub use context::ContextBudget;
+#[cfg(feature = "stable-structures")]
+pub use conversation::ConversationStore;
+pub use conversation::{Compaction, Conversation, ConversationSummary};
+pub use error::{LlmError, MessageSize};
+/// # Example
+///
+/// ```
+/// /// Lookup the balances of ICP accounts.
+/// #[ic_llm::tool]
+/// async fn lookup_icp_balances(
+///     /// The ICP accounts (64-character hex strings) to look up.
+///     accounts: Vec<String>,
+///     /// The number of decimals to show.
+///     decimals: Option<u32>,
+/// ) -> String {
+///     let decimals = decimals.unwrap_or(2) as usize;
+///     accounts
+///         .iter()
+///         .map(|account| format!("Balance of {account} is {:.*} ICP", decimals, 1.0))
+///         .collect::<Vec<_>>()
+///         .join("\n")
+/// }
+///
+/// fn main() {
+///     // `lookup_icp_balances::tool()` is the `Tool` definition, and
+///     // `lookup_icp_balances::call` parses a `FunctionCall` and calls the function.
+///     let registry = ic_llm::ToolRegistry::new()
+///         .with_tool(lookup_icp_balances::tool(), lookup_icp_balances::call);
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use ic_llm_macros::tool;
+pub use json_schema::SchemaError;
+pub use model::{register_model_capabilities, Model, ModelCapabilities, ParseModelError};
+pub use registry::{IntoToolResult, ToolError, ToolRegistry};
+pub use retry::{RetryOn, RetryPolicy};
 pub use tool::{
-    Function, ParameterBuilder, ParameterType, Parameters, Property, Tool, ToolBuilder,
+    Bound, Function, Items, ParameterBuilder, ParameterType, Parameters, Property, Tool,
+    ToolBuilder, ToolChoice,
 };
+pub use transport::{
+    clear_transport, set_transport, CanisterTransport, LlmTransport, TransportFuture, WaitMode,
+};
+pub use validation::{validate_tool_call, validate_tool_calls, InvalidToolCall, Violation};
+
+/// Items used by the code generated by `#[tool]`.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    /// The types of tool arguments that are parsed with `FromStr`.
+    #[diagnostic::on_unimplemented(
+        message = "`{Self}` cannot be the type of a tool argument",
+        label = "unsupported tool argument type",
+        note = "tool arguments must implement `FromStr`, or be `Vec`s of booleans, numbers, strings or `Vec`s"
+    )]
+    pub trait ParsedArgument {}
+
+    impl<T: std::str::FromStr> ParsedArgument for T {}
+}
 
 // The mainnet principal of the LLM canister.
 const MAINNET_LLM_CANISTER: &str = "w36hm-eqaaa-aaaal-qr76a-cai";
@@ -19,8 +95,8 @@ const MAINNET_LLM_CANISTER: &str = "w36hm-eqaaa-aaaal-qr76a-cai";
 /// by `icp deploy`) and otherwise falls back to the mainnet canister.
 pub(crate) fn default_llm_canister() -> Principal {
     // The env-var lookup only works in a canister.
-    // Skip in unit tests.
-    #[cfg(not(test))]
+    // Skip in unit tests and off-chain, e.g. in tests using a mock transport.
+    #[cfg(all(target_family = "wasm", not(test)))]
     {
         const LLM_CANISTER_ENV: &str = "PUBLIC_CANISTER_ID:llm";
         if ic_cdk::api::env_var_name_exists(LLM_CANISTER_ENV) {
@@ -32,25 +108,6 @@ pub(crate) fn default_llm_canister() -> Principal {
     Principal::from_text(MAINNET_LLM_CANISTER).unwrap()
 }
 
-/// Supported LLM models.
-#[derive(Debug)]
-pub enum Model {
-    Llama3_1_8B,
-    Qwen3_32B,
-    Llama4Scout,
-}
-
-impl fmt::Display for Model {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let text = match self {
-            Model::Llama3_1_8B => "llama3.1:8b",
-            Model::Qwen3_32B => "qwen3:32b",
-            Model::Llama4Scout => "llama4-scout",
-        };
-        write!(f, "{}", text)
-    }
-}
-
 /// Sends a single message to a model.
 ///
 /// # Example
@@ -73,6 +130,28 @@ pub async fn prompt<P: ToString>(model: Model, prompt_str: P) -> String {
     response.message.content.unwrap_or_default()
 }
 
+/// Sends a single message to a model, returning an error if the request fails.
+///
+/// # Example
+///
+/// ```
+/// use ic_llm::{LlmError, Model};
+///
+/// # async fn try_prompt_example() -> Result<String, LlmError> {
+/// ic_llm::try_prompt(Model::Llama3_1_8B, "What's the speed of light?").await
+/// # }
+/// ```
+pub async fn try_prompt<P: ToString>(model: Model, prompt_str: P) -> Result<String, LlmError> {
+    let response = ChatBuilder::new(model)
+        .with_messages(vec![ChatMessage::User {
+            content: prompt_str.to_string(),
+        }])
+        .try_send()
+        .await?;
+
+    Ok(response.message.content.unwrap_or_default())
+}
+
 /// Creates a new ChatBuilder with the specified model.
 ///
 /// This is a convenience function that returns a ChatBuilder instance initialized with the given model.
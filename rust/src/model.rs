@@ -0,0 +1,327 @@
+use candid::types::{Serializer, Type, TypeInner};
+use candid::CandidType;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// Supported LLM models.
+///
+/// Models that the LLM canister supports but that this crate doesn't know about yet
+/// can be used with [`Model::Custom`]. New variants may be added in minor releases.
+///
+/// A model is converted to and from its name with `Display` and `FromStr`, and is
+/// encoded as its name with serde and Candid, so it can be accepted from callers:
+///
+/// ```
+/// use ic_llm::Model;
+///
+/// assert_eq!("qwen3:32b".parse::<Model>(), Ok(Model::Qwen3_32B));
+/// assert_eq!(
+///     "some-new-model".parse::<Model>(),
+///     Ok(Model::Custom("some-new-model".to_string()))
+/// );
+/// ```
+///
+/// Models are compared by name, so a [`Model::Custom`] with the name of a known
+/// model is equal to that model:
+///
+/// ```
+/// use ic_llm::Model;
+///
+/// assert_eq!(Model::Custom("qwen3:32b".to_string()), Model::Qwen3_32B);
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Model {
+    Llama3_1_8B,
+    Qwen3_32B,
+    Llama4Scout,
+    /// A model that is referred to by the name the LLM canister knows it by.
+    Custom(String),
+}
+
+impl Model {
+    /// The models known to this crate.
+    const KNOWN: [Model; 3] = [Model::Llama3_1_8B, Model::Qwen3_32B, Model::Llama4Scout];
+
+    /// Returns the name of the model, as expected by the LLM canister.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Model::Llama3_1_8B => "llama3.1:8b",
+            Model::Qwen3_32B => "qwen3:32b",
+            Model::Llama4Scout => "llama4-scout",
+            Model::Custom(name) => name,
+        }
+    }
+
+    /// Returns the known model with the same name, if any.
+    fn known(&self) -> Option<Model> {
+        Self::KNOWN
+            .into_iter()
+            .find(|model| model.as_str() == self.as_str())
+    }
+
+    /// Returns what the model can do, or `None` for a custom model whose
+    /// capabilities haven't been registered with [`register_model_capabilities`].
+    ///
+    /// ```
+    /// use ic_llm::Model;
+    ///
+    /// let capabilities = Model::Qwen3_32B.capabilities().unwrap();
+    /// assert!(capabilities.supports_tools);
+    /// assert!(capabilities.reasoning);
+    /// ```
+    pub fn capabilities(&self) -> Option<ModelCapabilities> {
+        if let Some(capabilities) =
+            REGISTERED_CAPABILITIES.with_borrow(|registered| registered.get(self.as_str()).copied())
+        {
+            return Some(capabilities);
+        }
+        match self.known()? {
+            Model::Llama3_1_8B => Some(ModelCapabilities {
+                context_window: 131_072,
+                supports_tools: true,
+                reasoning: false,
+                multimodal: false,
+                max_output_tokens: 4_096,
+            }),
+            Model::Qwen3_32B => Some(ModelCapabilities {
+                context_window: 32_768,
+                supports_tools: true,
+                reasoning: true,
+                multimodal: false,
+                max_output_tokens: 8_192,
+            }),
+            // The model itself supports a context of up to 10M tokens, far more than
+            // fits in a request to the LLM canister.
+            Model::Llama4Scout => Some(ModelCapabilities {
+                context_window: 131_072,
+                supports_tools: true,
+                reasoning: false,
+                multimodal: true,
+                max_output_tokens: 4_096,
+            }),
+            Model::Custom(_) => None,
+        }
+    }
+
+    /// Roughly estimates the number of tokens that the model's tokenizer splits
+    /// `text` into.
+    ///
+    /// The estimate assumes the average number of bytes per token that the
+    /// tokenizer achieves on English text, so it is only an approximation.
+    ///
+    /// ```
+    /// use ic_llm::Model;
+    ///
+    /// assert_eq!(Model::Llama3_1_8B.estimate_tokens("How big is the sun?"), 5);
+    /// ```
+    pub fn estimate_tokens(&self, text: &str) -> u64 {
+        // In tenths of a byte.
+        let bytes_per_token = match self.known() {
+            Some(Model::Qwen3_32B) => 35,
+            _ => 40,
+        };
+        (text.len() as u64 * 10).div_ceil(bytes_per_token)
+    }
+}
+
+/// What a model can do, as returned by [`Model::capabilities`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// The maximum number of tokens of a request and its response combined.
+    pub context_window: u64,
+    /// Whether the model reliably produces well-formed tool calls.
+    pub supports_tools: bool,
+    /// Whether the model emits its reasoning in `<think>` blocks before answering.
+    pub reasoning: bool,
+    /// Whether the model accepts images as well as text.
+    pub multimodal: bool,
+    /// The recommended maximum number of tokens to generate in a response.
+    pub max_output_tokens: u64,
+}
+
+thread_local! {
+    /// Registered capabilities by model name.
+    static REGISTERED_CAPABILITIES: RefCell<HashMap<String, ModelCapabilities>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Registers the capabilities of a model, typically a [`Model::Custom`] one.
+///
+/// The registered capabilities are returned by [`Model::capabilities`] instead of
+/// the built-in ones, so this can also be used to override those. Capabilities
+/// apply to all models with the same name.
+pub fn register_model_capabilities(model: Model, capabilities: ModelCapabilities) {
+    REGISTERED_CAPABILITIES.with_borrow_mut(|registered| {
+        registered.insert(model.as_str().to_string(), capabilities);
+    });
+}
+
+impl PartialEq for Model {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Model {}
+
+impl Hash for Model {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The error returned when parsing an empty model name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseModelError;
+
+impl fmt::Display for ParseModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "model name must not be empty")
+    }
+}
+
+impl std::error::Error for ParseModelError {}
+
+impl FromStr for Model {
+    type Err = ParseModelError;
+
+    /// Parses a model name. Names of known models map to their variant, and all
+    /// other names to [`Model::Custom`].
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ParseModelError);
+        }
+        let model = Model::Custom(name.to_string());
+        Ok(model.known().unwrap_or(model))
+    }
+}
+
+impl Serialize for Model {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Model {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl CandidType for Model {
+    fn _ty() -> Type {
+        TypeInner::Text.into()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        serializer.serialize_text(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_model_names() {
+        for model in Model::KNOWN {
+            assert_eq!(model.to_string().parse(), Ok(model));
+        }
+        assert_eq!(
+            " llama3.3:70b ".parse(),
+            Ok(Model::Custom("llama3.3:70b".to_string()))
+        );
+        assert_eq!("".parse::<Model>(), Err(ParseModelError));
+    }
+
+    #[test]
+    fn compares_models_by_name() {
+        use std::collections::HashSet;
+
+        let custom = Model::Custom("llama4-scout".to_string());
+        assert_eq!(custom, Model::Llama4Scout);
+        assert_ne!(Model::Custom("mistral:7b".to_string()), Model::Llama4Scout);
+        assert_eq!(
+            HashSet::from([custom, Model::Llama4Scout, Model::Qwen3_32B]).len(),
+            2
+        );
+        let text = "How big is the sun?";
+        assert_eq!(
+            Model::Custom("qwen3:32b".to_string()).estimate_tokens(text),
+            Model::Qwen3_32B.estimate_tokens(text)
+        );
+    }
+
+    #[test]
+    fn known_models_have_capabilities() {
+        for model in Model::KNOWN {
+            let capabilities = model.capabilities().unwrap();
+            assert!(capabilities.max_output_tokens < capabilities.context_window);
+        }
+        assert!(Model::Llama4Scout.capabilities().unwrap().multimodal);
+        assert!(!Model::Llama3_1_8B.capabilities().unwrap().reasoning);
+        assert_eq!(Model::Custom("mistral:7b".to_string()).capabilities(), None);
+    }
+
+    #[test]
+    fn registered_capabilities_take_precedence() {
+        let custom = Model::Custom("mistral:7b".to_string());
+        let capabilities = ModelCapabilities {
+            context_window: 8_192,
+            supports_tools: false,
+            reasoning: false,
+            multimodal: false,
+            max_output_tokens: 1_024,
+        };
+        register_model_capabilities(custom.clone(), capabilities);
+        register_model_capabilities(
+            Model::Custom("qwen3:32b".to_string()),
+            ModelCapabilities {
+                context_window: 131_072,
+                ..Model::Qwen3_32B.capabilities().unwrap()
+            },
+        );
+
+        assert_eq!(custom.capabilities(), Some(capabilities));
+        // Capabilities registered for a name apply to the known model of that name.
+        assert_eq!(
+            Model::Qwen3_32B.capabilities().unwrap().context_window,
+            131_072
+        );
+        assert_eq!(
+            Model::Custom("llama3.1:8b".to_string()).capabilities(),
+            Model::Llama3_1_8B.capabilities()
+        );
+    }
+
+    #[test]
+    fn encodes_models_as_names() {
+        let models = vec![Model::Llama4Scout, Model::Custom("mistral:7b".to_string())];
+
+        let json = serde_json::to_string(&models).unwrap();
+        assert_eq!(json, r#"["llama4-scout","mistral:7b"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Model>>(&json).unwrap(), models);
+        assert!(serde_json::from_str::<Model>(r#""""#).is_err());
+
+        let bytes = candid::encode_one(&models).unwrap();
+        assert_eq!(candid::decode_one::<Vec<Model>>(&bytes).unwrap(), models);
+        assert_eq!(
+            candid::decode_one::<Vec<String>>(&bytes).unwrap(),
+            vec!["llama4-scout", "mistral:7b"]
+        );
+    }
+}
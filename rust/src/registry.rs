@@ -0,0 +1,302 @@
+use crate::arguments::ArgumentError;
+use crate::chat::{ChatMessage, FunctionCall, ToolCall};
+use crate::tool::Tool;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// An error returned by a tool handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolError {
+    /// The model called a tool that isn't registered.
+    UnknownTool(String),
+    /// The arguments of the tool call are invalid.
+    InvalidArguments(ArgumentError),
+    /// The tool failed to produce a result.
+    Failed(String),
+}
+
+impl ToolError {
+    /// Creates an error for a tool that failed with the given message.
+    pub fn failed<S: Into<String>>(message: S) -> Self {
+        ToolError::Failed(message.into())
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ToolError::UnknownTool(name) => write!(f, "unknown tool: {name}"),
+            ToolError::InvalidArguments(e) => write!(f, "{e}"),
+            ToolError::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ToolError::InvalidArguments(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArgumentError> for ToolError {
+    fn from(e: ArgumentError) -> Self {
+        ToolError::InvalidArguments(e)
+    }
+}
+
+/// Conversion of the value returned by a tool function into the result of the tool call.
+///
+/// This is used by the code generated by the `#[tool]` macro.
+pub trait IntoToolResult {
+    fn into_tool_result(self) -> Result<String, ToolError>;
+}
+
+impl IntoToolResult for String {
+    fn into_tool_result(self) -> Result<String, ToolError> {
+        Ok(self)
+    }
+}
+
+impl IntoToolResult for Result<String, ToolError> {
+    fn into_tool_result(self) -> Result<String, ToolError> {
+        self
+    }
+}
+
+impl IntoToolResult for Result<String, String> {
+    fn into_tool_result(self) -> Result<String, ToolError> {
+        self.map_err(ToolError::Failed)
+    }
+}
+
+type ToolFuture = Pin<Box<dyn Future<Output = Result<String, ToolError>>>>;
+type ToolHandler = Rc<dyn Fn(FunctionCall) -> ToolFuture>;
+
+/// A set of tools, each paired with the handler that executes calls to it.
+///
+/// Keeping the schema of a tool next to its handler ensures that every tool
+/// offered to the model can also be dispatched.
+///
+/// # Example
+///
+/// ```
+/// use ic_llm::{ChatMessage, Model, ParameterType, ToolRegistry};
+///
+/// # async fn registry_example() {
+/// let registry = ToolRegistry::new().with_tool(
+///     ic_llm::tool("get_weather")
+///         .with_parameter(ic_llm::parameter("location", ParameterType::String).is_required())
+///         .build(),
+///     |call| async move {
+///         let location = call.get("location").unwrap_or_default();
+///         Ok(format!("Weather in {location}: Sunny, 72°F"))
+///     },
+/// );
+///
+/// let mut messages = vec![ChatMessage::User {
+///     content: "What's the weather in Cairo?".to_string(),
+/// }];
+/// let response = ic_llm::chat(Model::Llama3_1_8B)
+///     .with_messages(messages.clone())
+///     .with_tools(registry.tools())
+///     .send()
+///     .await;
+///
+/// messages.push(ChatMessage::Assistant(response.message.clone()));
+/// for tool_call in &response.message.tool_calls {
+///     messages.push(registry.execute(tool_call).await);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    entries: Vec<(Tool, ToolHandler)>,
+}
+
+impl ToolRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tool, together with the handler that executes calls to it.
+    ///
+    /// A previously registered tool with the same name is replaced.
+    pub fn with_tool<F, Fut>(mut self, tool: Tool, handler: F) -> Self
+    where
+        F: Fn(FunctionCall) -> Fut + 'static,
+        Fut: Future<Output = Result<String, ToolError>> + 'static,
+    {
+        let handler: ToolHandler = Rc::new(move |call| Box::pin(handler(call)));
+        let name = tool_name(&tool).to_string();
+        self.entries.retain(|(tool, _)| tool_name(tool) != name);
+        self.entries.push((tool, handler));
+        self
+    }
+
+    /// Returns the registered tools, to be passed to [`ChatBuilder::with_tools`](crate::ChatBuilder::with_tools).
+    pub fn tools(&self) -> Vec<Tool> {
+        self.entries.iter().map(|(tool, _)| tool.clone()).collect()
+    }
+
+    /// Returns the tool with the given name.
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.entries
+            .iter()
+            .map(|(tool, _)| tool)
+            .find(|tool| tool_name(tool) == name)
+    }
+
+    /// Returns the number of registered tools.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no tools are registered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Executes a tool call with the handler of the tool it names.
+    pub async fn dispatch(&self, tool_call: &ToolCall) -> Result<String, ToolError> {
+        let name = &tool_call.function.name;
+        let (_, handler) = self
+            .entries
+            .iter()
+            .find(|(tool, _)| tool_name(tool) == name)
+            .ok_or_else(|| ToolError::UnknownTool(name.clone()))?;
+        handler(tool_call.function.clone()).await
+    }
+
+    /// Executes a tool call and returns the tool message answering it.
+    ///
+    /// Errors are reported in the content of the message, so that the model can
+    /// react to them.
+    pub async fn execute(&self, tool_call: &ToolCall) -> ChatMessage {
+        let content = match self.dispatch(tool_call).await {
+            Ok(result) => result,
+            Err(e) => format!("Error: {e}"),
+        };
+        ChatMessage::Tool {
+            content,
+            tool_call_id: tool_call.id.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|(tool, _)| tool))
+            .finish()
+    }
+}
+
+fn tool_name(tool: &Tool) -> &str {
+    match tool {
+        Tool::Function(function) => &function.name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, tool_call};
+    use crate::ToolBuilder;
+
+    fn registry() -> ToolRegistry {
+        ToolRegistry::new()
+            .with_tool(
+                ToolBuilder::new("get_weather")
+                    .with_description("Get the weather")
+                    .build(),
+                |call| async move {
+                    let location: String = call.get_as("location")?;
+                    Ok(format!("Sunny in {location}"))
+                },
+            )
+            .with_tool(ToolBuilder::new("get_time").build(), |_| async {
+                Ok("12:00".to_string())
+            })
+    }
+
+    #[test]
+    fn lists_registered_tools() {
+        let registry = registry();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.tools(),
+            vec![
+                ToolBuilder::new("get_weather")
+                    .with_description("Get the weather")
+                    .build(),
+                ToolBuilder::new("get_time").build(),
+            ]
+        );
+        assert_eq!(
+            registry.get("get_time"),
+            Some(&ToolBuilder::new("get_time").build())
+        );
+        assert_eq!(registry.get("get_date"), None);
+        assert!(ToolRegistry::new().is_empty());
+    }
+
+    #[test]
+    fn registering_a_tool_again_replaces_it() {
+        let registry = registry().with_tool(ToolBuilder::new("get_time").build(), |_| async {
+            Ok("13:00".to_string())
+        });
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            block_on(registry.dispatch(&tool_call("call_1", "get_time", &[]))),
+            Ok("13:00".to_string())
+        );
+    }
+
+    #[test]
+    fn dispatches_by_name() {
+        let registry = registry();
+        assert_eq!(
+            block_on(registry.dispatch(&tool_call(
+                "call_1",
+                "get_weather",
+                &[("location", "Cairo")]
+            ))),
+            Ok("Sunny in Cairo".to_string())
+        );
+        assert_eq!(
+            block_on(registry.dispatch(&tool_call("call_2", "get_weather", &[]))),
+            Err(ToolError::InvalidArguments(ArgumentError::Missing(
+                "location".to_string()
+            )))
+        );
+        assert_eq!(
+            block_on(registry.dispatch(&tool_call("call_3", "get_date", &[]))),
+            Err(ToolError::UnknownTool("get_date".to_string()))
+        );
+    }
+
+    #[test]
+    fn execute_returns_tool_messages() {
+        let registry = registry();
+        assert_eq!(
+            block_on(registry.execute(&tool_call("call_1", "get_time", &[]))),
+            ChatMessage::Tool {
+                content: "12:00".to_string(),
+                tool_call_id: "call_1".to_string(),
+            }
+        );
+        assert_eq!(
+            block_on(registry.execute(&tool_call("call_2", "get_date", &[]))),
+            ChatMessage::Tool {
+                content: "Error: unknown tool: get_date".to_string(),
+                tool_call_id: "call_2".to_string(),
+            }
+        );
+    }
+}
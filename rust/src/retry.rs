@@ -0,0 +1,251 @@
+use crate::error::LlmError;
+use candid::Principal;
+use ic_cdk::call::Call;
+use std::future::Future;
+use std::time::Duration;
+
+/// A class of failures that a [`RetryPolicy`] may retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryOn {
+    /// Transient system rejects, see [`LlmError::Transient`].
+    Transient,
+    /// Calls that timed out, see [`LlmError::Timeout`].
+    Timeout,
+    /// Rejects and traps of the LLM canister, see [`LlmError::CanisterError`].
+    CanisterError,
+}
+
+/// Policy for retrying failed requests to the LLM canister.
+///
+/// By default, transient rejects and timeouts are retried up to three attempts
+/// in total, without any delay between attempts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    retry_on: Vec<RetryOn>,
+    delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that makes at most `max_attempts` attempts in total.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            retry_on: vec![RetryOn::Transient, RetryOn::Timeout],
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Creates a policy that never retries.
+    pub fn never() -> Self {
+        Self::new(1)
+    }
+
+    /// Sets the classes of failures that are retried.
+    pub fn with_retry_on<I: IntoIterator<Item = RetryOn>>(mut self, retry_on: I) -> Self {
+        self.retry_on = retry_on.into_iter().collect();
+        self
+    }
+
+    /// Waits before each retry, doubling the delay after every failed attempt.
+    ///
+    /// The wait is up to the transport, see [`LlmTransport::wait`](crate::LlmTransport::wait).
+    /// Requests to the LLM canister wait by calling the management canister until
+    /// the delay has passed, which keeps the current message open, so that retries
+    /// with a delay also work from update methods that answer a caller.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// The maximum number of attempts, including the first one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns whether the policy retries the given error.
+    pub fn is_retryable(&self, error: &LlmError) -> bool {
+        let class = match error {
+            LlmError::Transient(_) => RetryOn::Transient,
+            LlmError::Timeout(_) => RetryOn::Timeout,
+            LlmError::CanisterError(_) => RetryOn::CanisterError,
+            _ => return false,
+        };
+        self.retry_on.contains(&class)
+    }
+
+    /// The delay before the given retry, starting at 1 for the first retry.
+    fn delay_before(&self, retry: u32) -> Duration {
+        self.delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails with an error that is not retryable,
+/// or the policy runs out of attempts.
+/// Waits for the delays between attempts with `wait`.
+pub(crate) async fn with_retries<T, F, Fut, W, WaitFut>(
+    policy: &RetryPolicy,
+    mut attempt: F,
+    wait: W,
+) -> Result<T, LlmError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, LlmError>>,
+    W: Fn(Duration) -> WaitFut,
+    WaitFut: Future<Output = ()>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match attempt().await {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+
+        if !policy.is_retryable(&error) || policy.max_attempts == 1 {
+            return Err(error);
+        }
+        if attempts >= policy.max_attempts {
+            return Err(LlmError::RetriesExhausted {
+                attempts,
+                policy: policy.clone(),
+                last_error: Box::new(error),
+            });
+        }
+
+        let delay = policy.delay_before(attempts);
+        if !delay.is_zero() {
+            wait(delay).await;
+        }
+    }
+}
+
+/// Waits for at least `delay` by calling the management canister's `raw_rand`
+/// until the delay has passed.
+///
+/// Unlike a timer, an outstanding call keeps the current call context open, so the
+/// message isn't ended while it waits. Stops waiting early if a call fails.
+pub(crate) async fn wait_with_calls(delay: Duration) {
+    let deadline = ic_cdk::api::time().saturating_add(delay.as_nanos() as u64);
+    while ic_cdk::api::time() < deadline {
+        if Call::bounded_wait(Principal::management_canister(), "raw_rand")
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+    use std::cell::Cell;
+    use std::future::{ready, Ready};
+
+    fn no_wait(_: Duration) -> Ready<()> {
+        ready(())
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.max_attempts(), 3);
+        assert!(policy.is_retryable(&LlmError::Transient("".to_string())));
+        assert!(policy.is_retryable(&LlmError::Timeout("".to_string())));
+        assert!(!policy.is_retryable(&LlmError::CanisterError("".to_string())));
+        assert!(!policy.is_retryable(&LlmError::Decode("".to_string())));
+    }
+
+    #[test]
+    fn custom_retry_classes() {
+        let policy = RetryPolicy::new(2).with_retry_on([RetryOn::CanisterError]);
+        assert!(policy.is_retryable(&LlmError::CanisterError("".to_string())));
+        assert!(!policy.is_retryable(&LlmError::Transient("".to_string())));
+    }
+
+    #[test]
+    fn delay_doubles_after_each_retry() {
+        let policy = RetryPolicy::new(4).with_delay(Duration::from_secs(1));
+        assert_eq!(policy.delay_before(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_before(2), Duration::from_secs(2));
+        assert_eq!(policy.delay_before(3), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn retries_until_success() {
+        let errors = [
+            LlmError::Transient("busy".to_string()),
+            LlmError::Timeout("slow".to_string()),
+        ];
+        let calls = Cell::new(0);
+        let result = block_on(with_retries(
+            &RetryPolicy::default(),
+            || {
+                let call = calls.get();
+                calls.set(call + 1);
+                let result = errors.get(call).cloned().map_or(Ok(call), Err);
+                async move { result }
+            },
+            no_wait,
+        ));
+        assert_eq!(result, Ok(2));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn reports_policy_when_attempts_are_exhausted() {
+        let policy = RetryPolicy::new(2);
+        let calls = Cell::new(0);
+        let result: Result<(), _> = block_on(with_retries(
+            &policy,
+            || {
+                calls.set(calls.get() + 1);
+                async { Err(LlmError::Transient("busy".to_string())) }
+            },
+            no_wait,
+        ));
+        assert_eq!(
+            result,
+            Err(LlmError::RetriesExhausted {
+                attempts: 2,
+                policy: policy.clone(),
+                last_error: Box::new(LlmError::Transient("busy".to_string())),
+            })
+        );
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = block_on(with_retries(
+            &RetryPolicy::default(),
+            || {
+                calls.set(calls.get() + 1);
+                async { Err(LlmError::Decode("bad".to_string())) }
+            },
+            no_wait,
+        ));
+        assert_eq!(result, Err(LlmError::Decode("bad".to_string())));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn never_returns_the_original_error() {
+        let result: Result<(), _> = block_on(with_retries(
+            &RetryPolicy::never(),
+            || async { Err(LlmError::Transient("busy".to_string())) },
+            no_wait,
+        ));
+        assert_eq!(result, Err(LlmError::Transient("busy".to_string())));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::chat::{ChatMessage, Request};
+use crate::context::truncate;
+use crate::error::{LlmError, MessageSize, MAX_REQUEST_BYTES};
+
+/// How many of the largest messages are reported by [`LlmError::RequestTooLarge`].
+const REPORTED_MESSAGES: usize = 3;
+
+/// Encodes the request, checking that it fits into an inter-canister message.
+///
+/// If it doesn't and `tool_content_limit` is set, the contents of tool messages are
+/// truncated to that many bytes and the request is encoded again.
+pub(crate) fn encode(
+    request: &mut Request,
+    tool_content_limit: Option<usize>,
+) -> Result<Vec<u8>, LlmError> {
+    let mut args = encode_request(request)?;
+    if args.len() > MAX_REQUEST_BYTES {
+        if let Some(limit) = tool_content_limit {
+            let mut truncated = false;
+            for message in &mut request.messages {
+                if let ChatMessage::Tool { content, .. } = message {
+                    truncated |= truncate(content, limit);
+                }
+            }
+            if truncated {
+                args = encode_request(request)?;
+            }
+        }
+    }
+
+    if args.len() > MAX_REQUEST_BYTES {
+        Err(too_large(request, args.len()))
+    } else {
+        Ok(args)
+    }
+}
+
+fn encode_request(request: &Request) -> Result<Vec<u8>, LlmError> {
+    candid::encode_one(request).map_err(|e| LlmError::Encode(e.to_string()))
+}
+
+/// Returns the error for a request whose encoding takes `size` bytes.
+///
+/// The sizes of the messages are approximated by the lengths of their texts, which
+/// make up almost all of their encoding, rather than encoding each message.
+fn too_large(request: &Request, size: usize) -> LlmError {
+    let mut largest_messages: Vec<_> = request
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| MessageSize {
+            index,
+            bytes: text_size(message),
+        })
+        .collect();
+    largest_messages.sort_by_key(|message| std::cmp::Reverse(message.bytes));
+    largest_messages.truncate(REPORTED_MESSAGES);
+
+    LlmError::RequestTooLarge {
+        size,
+        limit: MAX_REQUEST_BYTES,
+        largest_messages,
+    }
+}
+
+/// Returns the number of bytes of the texts of a message.
+fn text_size(message: &ChatMessage) -> usize {
+    match message {
+        ChatMessage::System { content } | ChatMessage::User { content } => content.len(),
+        ChatMessage::Tool {
+            content,
+            tool_call_id,
+        } => content.len() + tool_call_id.len(),
+        ChatMessage::Assistant(assistant) => {
+            let calls: usize = assistant
+                .tool_calls
+                .iter()
+                .map(|call| {
+                    call.id.len()
+                        + call.function.name.len()
+                        + call
+                            .function
+                            .arguments
+                            .iter()
+                            .map(|argument| argument.name.len() + argument.value.len())
+                            .sum::<usize>()
+                })
+                .sum();
+            assistant.content.as_ref().map_or(0, String::len) + calls
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(tool_output_bytes: usize) -> Request {
+        Request {
+            model: "llama3.1:8b".to_string(),
+            messages: vec![
+                ChatMessage::User {
+                    content: "Summarize the page".to_string(),
+                },
+                ChatMessage::Tool {
+                    content: "x".repeat(tool_output_bytes),
+                    tool_call_id: "call_1".to_string(),
+                },
+                ChatMessage::User {
+                    content: "y".repeat(1_000),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_requests_within_the_limit() {
+        let mut small = request(1_000);
+        assert_eq!(
+            encode(&mut small, None),
+            Ok(candid::encode_one(request(1_000)).unwrap())
+        );
+        assert_eq!(small, request(1_000));
+    }
+
+    #[test]
+    fn reports_the_largest_messages() {
+        let mut large = request(MAX_REQUEST_BYTES);
+        let Err(LlmError::RequestTooLarge {
+            size,
+            limit,
+            largest_messages,
+        }) = encode(&mut large, None)
+        else {
+            panic!("expected the request to be too large");
+        };
+
+        assert_eq!(
+            size,
+            candid::encode_one(request(MAX_REQUEST_BYTES))
+                .unwrap()
+                .len()
+        );
+        assert_eq!(limit, MAX_REQUEST_BYTES);
+        let indices: Vec<_> = largest_messages.iter().map(|m| m.index).collect();
+        assert_eq!(indices, [1, 2, 0]);
+        assert!(largest_messages[0].bytes > MAX_REQUEST_BYTES);
+    }
+
+    #[test]
+    fn truncates_tool_contents_if_allowed() {
+        let mut large = request(MAX_REQUEST_BYTES);
+        assert!(encode(&mut large, Some(10_000)).is_ok());
+
+        let ChatMessage::Tool { content, .. } = &large.messages[1] else {
+            panic!("expected a tool message");
+        };
+        assert_eq!(content.len(), 10_000);
+        assert!(content.ends_with(" [truncated]"));
+        assert_eq!(large.messages[2], request(0).messages[2]);
+
+        // Other messages are never truncated.
+        let mut large = request(0);
+        large.messages[2] = ChatMessage::User {
+            content: "y".repeat(MAX_REQUEST_BYTES),
+        };
+        assert!(encode(&mut large, Some(10_000)).is_err());
+    }
+}
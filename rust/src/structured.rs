@@ -0,0 +1,409 @@
+use crate::json_schema::parameters_schema;
+use crate::tool::{Items, Parameters, Property};
+use crate::validation::unmet_constraint;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// The default number of times an answer that cannot be parsed is re-requested.
+pub(crate) const DEFAULT_REPAIR_ATTEMPTS: u32 = 2;
+
+/// Returns the instruction asking the model to answer in JSON.
+pub(crate) fn instruction(schema: Option<&Parameters>) -> String {
+    match schema {
+        Some(schema) => format!(
+            "Answer only with a JSON object that matches the following JSON schema, \
+             without any other text:\n{}",
+            parameters_schema(schema)
+        ),
+        None => "Answer only with JSON, without any other text.".to_string(),
+    }
+}
+
+/// Returns the message asking the model to correct an answer that couldn't be parsed.
+pub(crate) fn repair_prompt(error: &str) -> String {
+    format!(
+        "Your previous answer could not be used: {error}. \
+         Answer again with only the corrected JSON."
+    )
+}
+
+/// Extracts the JSON value from the content of an answer, checks it against the
+/// schema and deserializes it.
+pub(crate) fn parse<T: DeserializeOwned>(
+    content: &str,
+    schema: Option<&Parameters>,
+) -> Result<T, String> {
+    let mut value = extract_json(content).ok_or("the answer doesn't contain any JSON")?;
+    if let Some(schema) = schema {
+        let errors = validate(schema, &mut value);
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// Finds the JSON value in an answer, which models tend to wrap in code fences or
+/// surround with prose.
+fn extract_json(content: &str) -> Option<Value> {
+    let mut fences = content.split("```");
+    fences.next();
+    if let Some(fenced) = fences.next() {
+        // Skip the language tag, e.g. `json`.
+        let fenced = fenced.split_once('\n').map_or(fenced, |(_, code)| code);
+        if let Ok(value) = serde_json::from_str(fenced.trim()) {
+            return Some(value);
+        }
+    }
+
+    outermost_spans(content)
+        .into_iter()
+        .find_map(|(start, end)| serde_json::from_str(&content[start..=end]).ok())
+}
+
+/// Returns the start and end of the outermost balanced `{…}` and `[…]` spans, in
+/// order, in a single pass over `content`.
+///
+/// Brackets in JSON strings are skipped, and a closing bracket that doesn't match
+/// the innermost open one discards the open spans.
+fn outermost_spans(content: &str) -> Vec<(usize, usize)> {
+    let mut open = Vec::new();
+    let mut spans = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, byte) in content.bytes().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' if !open.is_empty() => in_string = true,
+            b'{' | b'[' => open.push((byte, i)),
+            b'}' | b']' => match open.pop() {
+                Some((b'{', start)) if byte == b'}' => spans.push((start, i)),
+                Some((b'[', start)) if byte == b']' => spans.push((start, i)),
+                Some(_) => open.clear(),
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    // Inner spans are closed before the spans around them.
+    spans.sort_unstable();
+    let mut outermost: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        if outermost
+            .last()
+            .is_none_or(|&(_, last_end)| start > last_end)
+        {
+            outermost.push((start, end));
+        }
+    }
+    outermost
+}
+
+/// Checks a JSON value against a schema, returning a description of each problem.
+fn validate(schema: &Parameters, value: &mut Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check_object(
+        schema.properties.as_deref(),
+        schema.required.as_deref(),
+        value,
+        "",
+        &mut errors,
+    );
+    errors
+}
+
+fn check_object(
+    properties: Option<&[Property]>,
+    required: Option<&[String]>,
+    value: &mut Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let Some(object) = value.as_object_mut() else {
+        errors.push(format!("{} must be an object", describe(path)));
+        return;
+    };
+    for name in required.unwrap_or_default() {
+        if !object.contains_key(name) {
+            errors.push(format!("missing required field `{path}/{name}`"));
+        }
+    }
+    for property in properties.unwrap_or_default() {
+        if let Some(value) = object.get_mut(&property.name) {
+            check_property(
+                property,
+                value,
+                &format!("{path}/{}", property.name),
+                errors,
+            );
+        }
+    }
+}
+
+fn check_property(property: &Property, value: &mut Value, path: &str, errors: &mut Vec<String>) {
+    if value.is_null() && property.nullable == Some(true) {
+        return;
+    }
+    if !has_type(&property.type_, value) {
+        errors.push(format!(
+            "{} must be of type {}",
+            describe(path),
+            property.type_
+        ));
+        return;
+    }
+
+    let text = match &*value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    if let Some(allowed) = &property.enum_ {
+        if !allowed.contains(&text) {
+            errors.push(format!(
+                "{} must be one of: {}",
+                describe(path),
+                allowed.join(", ")
+            ));
+            return;
+        }
+    }
+    if let Some(constraint) = unmet_constraint(property, &text) {
+        errors.push(format!("{} must be {constraint}", describe(path)));
+        return;
+    }
+
+    check_children(property, value, path, errors);
+}
+
+/// Checks the fields or elements of an `object` or `array` value of a property,
+/// e.g. of a tool call argument, returning a description of each problem.
+pub(crate) fn check_nested(property: &Property, value: &mut Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check_children(property, value, "", &mut errors);
+    errors
+}
+
+fn check_children(property: &Property, value: &mut Value, path: &str, errors: &mut Vec<String>) {
+    match value {
+        Value::Object(_) => check_object(
+            property.properties.as_deref(),
+            property.required.as_deref(),
+            value,
+            path,
+            errors,
+        ),
+        Value::Array(elements) => {
+            if let Some(items) = &property.items {
+                check_elements(items, elements, path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_elements(items: &Items, elements: &mut [Value], path: &str, errors: &mut Vec<String>) {
+    for (i, element) in elements.iter_mut().enumerate() {
+        let path = format!("{path}/{i}");
+        if !has_type(&items.type_, element) {
+            errors.push(format!(
+                "{} must be of type {}",
+                describe(&path),
+                items.type_
+            ));
+            continue;
+        }
+        match element {
+            Value::Object(_) => check_object(
+                items.properties.as_deref(),
+                items.required.as_deref(),
+                element,
+                &path,
+                errors,
+            ),
+            Value::Array(elements) => {
+                if let Some(items) = &items.items {
+                    check_elements(items, elements, &path, errors);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns whether `value` is of the given JSON schema type.
+///
+/// Whole numbers written as floats, e.g. `3.0`, are integers, and are turned into
+/// integers so that they can be deserialized into integer types.
+fn has_type(type_: &str, value: &mut Value) -> bool {
+    match type_ {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            if let Some(number) = value.as_f64().filter(|_| value.is_f64()) {
+                if number.fract() != 0.0 || number.abs() >= i64::MAX as f64 {
+                    return false;
+                }
+                *value = Value::from(number as i64);
+            }
+            value.is_i64() || value.is_u64()
+        }
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn describe(path: &str) -> String {
+    if path.is_empty() {
+        "the answer".to_string()
+    } else {
+        format!("`{path}`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::{ParameterBuilder, ParameterType, Tool, ToolBuilder};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Sentiment {
+        label: String,
+        score: f64,
+    }
+
+    fn schema() -> Parameters {
+        let Tool::Function(function) = ToolBuilder::new("sentiment")
+            .with_parameter(
+                ParameterBuilder::new("label", ParameterType::String)
+                    .with_enum_values(["positive", "negative"])
+                    .is_required(),
+            )
+            .with_parameter(
+                ParameterBuilder::new("score", ParameterType::Number)
+                    .with_minimum(0.0)
+                    .with_maximum(1.0)
+                    .is_required(),
+            )
+            .with_parameter(ParameterBuilder::new(
+                "spans",
+                ParameterType::array_of(ParameterType::Object(vec![ParameterBuilder::new(
+                    "start",
+                    ParameterType::Integer,
+                )
+                .is_required()])),
+            ))
+            .build();
+        function.parameters.unwrap()
+    }
+
+    #[test]
+    fn extracts_json_from_prose_and_fences() {
+        assert_eq!(
+            extract_json(r#"{"label": "positive"}"#),
+            Some(json!({ "label": "positive" }))
+        );
+        assert_eq!(
+            extract_json(
+                "Sure! Here it is:\n```json\n{\"label\": \"positive\"}\n```\nAnything else?"
+            ),
+            Some(json!({ "label": "positive" }))
+        );
+        assert_eq!(
+            extract_json("The [result] is {\"score\": 1} as requested."),
+            Some(json!({ "score": 1 }))
+        );
+        assert_eq!(
+            extract_json(r#"Using {"note": "a } in a string", "ok": [1, {"a": 2}]} here"#),
+            Some(json!({ "note": "a } in a string", "ok": [1, { "a": 2 }] }))
+        );
+        assert_eq!(
+            extract_json(r#"A stray ] and {"score": 1]} before [1, 2]"#),
+            Some(json!([1, 2]))
+        );
+        assert_eq!(extract_json("I don't know."), None);
+    }
+
+    #[test]
+    fn accepts_whole_numbers_as_integers() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Span {
+            start: u32,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Spans {
+            spans: Vec<Span>,
+        }
+
+        assert_eq!(
+            parse::<Spans>(
+                r#"{"label": "positive", "score": 1.0, "spans": [{"start": 3.0}]}"#,
+                Some(&schema())
+            ),
+            Ok(Spans {
+                spans: vec![Span { start: 3 }]
+            })
+        );
+        assert_eq!(
+            parse::<Spans>(
+                r#"{"label": "positive", "score": 1.0, "spans": [{"start": 3.5}]}"#,
+                Some(&schema())
+            ),
+            Err("`/spans/0/start` must be of type integer".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_valid_answers() {
+        assert_eq!(
+            parse::<Sentiment>(
+                r#"Result: {"label": "negative", "score": 0.25, "spans": [{"start": 3}]}"#,
+                Some(&schema())
+            ),
+            Ok(Sentiment {
+                label: "negative".to_string(),
+                score: 0.25,
+            })
+        );
+        assert_eq!(parse::<Vec<u32>>("[1, 2]", None), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn reports_schema_violations() {
+        assert_eq!(
+            parse::<Sentiment>(
+                r#"{"label": "neutral", "score": 2, "spans": [{"start": "x"}, {}]}"#,
+                Some(&schema())
+            ),
+            Err(
+                "`/label` must be one of: positive, negative; `/score` must be at most 1; \
+                 `/spans/0/start` must be of type integer; missing required field `/spans/1/start`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            parse::<Sentiment>("[]", Some(&schema())),
+            Err("the answer must be an object".to_string())
+        );
+        assert_eq!(
+            parse::<Sentiment>("Positive!", Some(&schema())),
+            Err("the answer doesn't contain any JSON".to_string())
+        );
+        assert!(parse::<Sentiment>(r#"{"label": "positive"}"#, None)
+            .unwrap_err()
+            .contains("missing field `score`"));
+    }
+}
@@ -0,0 +1,483 @@
+//! Utilities for testing agents without deploying the LLM canister.
+//!
+//! [`MockLlm`] is an [`LlmTransport`] that replays scripted responses and records
+//! every request it receives, so the logic around [`ChatBuilder`](crate::ChatBuilder)
+//! can be exercised deterministically in `cargo test`.
+//!
+//! Enable the `testing` feature to use this module.
+//!
+//! # Example
+//!
+//! ```
+//! use ic_llm::testing::{
+//!     block_on, text_response, tool_call, tool_calls_response, MockLlm, RequestMatcher,
+//! };
+//! use ic_llm::{Agent, ChatMessage, Model};
+//!
+//! let mock = MockLlm::new();
+//! mock.reply_when(
+//!     RequestMatcher::new().with_tool_result("call_1"),
+//!     text_response("It's sunny in Cairo."),
+//! )
+//! .reply(tool_calls_response(vec![tool_call(
+//!     "call_1",
+//!     "get_weather",
+//!     &[("location", "Cairo")],
+//! )]));
+//!
+//! let agent = Agent::new(ic_llm::chat(Model::Llama3_1_8B).with_transport(mock.clone()))
+//!     .with_tool(ic_llm::tool("get_weather").build(), |call| async move {
+//!         Ok(format!("Sunny in {}", call.get("location").unwrap_or_default()))
+//!     });
+//! let response = block_on(agent.run(vec![ChatMessage::User {
+//!     content: "Weather in Cairo?".to_string(),
+//! }]))
+//! .unwrap();
+//!
+//! assert_eq!(response.message.content.as_deref(), Some("It's sunny in Cairo."));
+//! mock.assert_request(0, RequestMatcher::new().with_message_containing("Cairo"));
+//! mock.assert_done();
+//! ```
+use crate::chat::{
+    AssistantMessage, ChatMessage, FunctionCall, Request, Response, ToolCall, ToolCallArgument,
+};
+use crate::error::LlmError;
+use crate::tool::Tool;
+use crate::transport::{LlmTransport, TransportFuture};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+type Predicate = Rc<dyn Fn(&Request) -> bool>;
+
+/// A predicate over [`Request`]s, used to select scripted replies and to assert
+/// on recorded requests.
+#[derive(Clone, Default)]
+pub struct RequestMatcher {
+    predicates: Vec<(String, Predicate)>,
+}
+
+impl RequestMatcher {
+    /// Creates a matcher that matches every request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches requests that use the given model.
+    pub fn with_model<S: Into<String>>(self, model: S) -> Self {
+        let model = model.into();
+        self.with(format!("model is {model:?}"), move |request| {
+            request.model == model
+        })
+    }
+
+    /// Matches requests with a message whose content contains the given text.
+    pub fn with_message_containing<S: Into<String>>(self, text: S) -> Self {
+        let text = text.into();
+        self.with(format!("a message contains {text:?}"), move |request| {
+            request
+                .messages
+                .iter()
+                .any(|message| message_content(message).is_some_and(|c| c.contains(&text)))
+        })
+    }
+
+    /// Matches requests whose last message is a user message containing the given text.
+    pub fn with_last_user_message_containing<S: Into<String>>(self, text: S) -> Self {
+        let text = text.into();
+        self.with(
+            format!("the last message is a user message containing {text:?}"),
+            move |request| {
+                matches!(
+                    request.messages.last(),
+                    Some(ChatMessage::User { content }) if content.contains(&text)
+                )
+            },
+        )
+    }
+
+    /// Matches requests that offer a tool with the given name.
+    pub fn with_tool<S: Into<String>>(self, name: S) -> Self {
+        let name = name.into();
+        self.with(format!("tool {name:?} is offered"), move |request| {
+            request
+                .tools
+                .iter()
+                .flatten()
+                .any(|Tool::Function(function)| function.name == name)
+        })
+    }
+
+    /// Matches requests that contain the result of the tool call with the given id.
+    pub fn with_tool_result<S: Into<String>>(self, tool_call_id: S) -> Self {
+        let tool_call_id = tool_call_id.into();
+        self.with(
+            format!("a tool message answers call {tool_call_id:?}"),
+            move |request| {
+                request.messages.iter().any(|message| match message {
+                    ChatMessage::Tool {
+                        tool_call_id: id, ..
+                    } => *id == tool_call_id,
+                    _ => false,
+                })
+            },
+        )
+    }
+
+    /// Matches requests that satisfy a custom predicate.
+    pub fn with<S, F>(mut self, description: S, predicate: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&Request) -> bool + 'static,
+    {
+        self.predicates
+            .push((description.into(), Rc::new(predicate)));
+        self
+    }
+
+    /// Returns whether the request satisfies every predicate of the matcher.
+    pub fn matches(&self, request: &Request) -> bool {
+        self.predicates
+            .iter()
+            .all(|(_, predicate)| predicate(request))
+    }
+
+    /// Describes the predicates that the request does not satisfy.
+    fn mismatches(&self, request: &Request) -> Vec<&str> {
+        self.predicates
+            .iter()
+            .filter(|(_, predicate)| !predicate(request))
+            .map(|(description, _)| description.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Debug for RequestMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.predicates.iter().map(|(description, _)| description))
+            .finish()
+    }
+}
+
+fn message_content(message: &ChatMessage) -> Option<&str> {
+    match message {
+        ChatMessage::User { content }
+        | ChatMessage::System { content }
+        | ChatMessage::Tool { content, .. } => Some(content),
+        ChatMessage::Assistant(message) => message.content.as_deref(),
+    }
+}
+
+struct ScriptedReply {
+    matcher: Option<RequestMatcher>,
+    result: Result<Response, LlmError>,
+}
+
+#[derive(Default)]
+struct MockState {
+    replies: VecDeque<ScriptedReply>,
+    requests: Vec<Request>,
+    waits: Vec<Duration>,
+}
+
+/// A scripted LLM backend for tests.
+///
+/// Each request is answered with the first scripted reply that it matches, which
+/// is then used up. Replies scripted with [`MockLlm::reply`] and [`MockLlm::fail`]
+/// match every request, and those scripted with [`MockLlm::reply_when`] only the
+/// requests that satisfy their matcher. The mock panics if a request matches no
+/// remaining reply.
+///
+/// The delays of a [`RetryPolicy`](crate::RetryPolicy) aren't waited for, but
+/// recorded, see [`MockLlm::waits`].
+///
+/// Clones of a `MockLlm` share their script and recorded requests, so a clone can
+/// be handed to [`ChatBuilder::with_transport`](crate::ChatBuilder::with_transport)
+/// or [`set_transport`](crate::set_transport) while the original is used for assertions.
+#[derive(Clone, Default)]
+pub struct MockLlm {
+    state: Rc<RefCell<MockState>>,
+}
+
+impl MockLlm {
+    /// Creates a mock without any scripted replies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scripts a reply with the given response, which answers the first request that
+    /// no earlier scripted reply matches.
+    pub fn reply(&self, response: Response) -> &Self {
+        self.script(None, Ok(response))
+    }
+
+    /// Scripts a reply with the given response, which answers the first request that
+    /// satisfies `matcher` and that no earlier scripted reply matches.
+    pub fn reply_when(&self, matcher: RequestMatcher, response: Response) -> &Self {
+        self.script(Some(matcher), Ok(response))
+    }
+
+    /// Scripts a failure with the given error, which answers the first request that
+    /// no earlier scripted reply matches.
+    pub fn fail(&self, error: LlmError) -> &Self {
+        self.script(None, Err(error))
+    }
+
+    fn script(&self, matcher: Option<RequestMatcher>, result: Result<Response, LlmError>) -> &Self {
+        self.state
+            .borrow_mut()
+            .replies
+            .push_back(ScriptedReply { matcher, result });
+        self
+    }
+
+    /// Returns all requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.borrow().requests.clone()
+    }
+
+    /// Returns the delays waited for before retries so far.
+    pub fn waits(&self) -> Vec<Duration> {
+        self.state.borrow().waits.clone()
+    }
+
+    /// Returns the number of scripted replies that have not been used yet.
+    pub fn remaining_replies(&self) -> usize {
+        self.state.borrow().replies.len()
+    }
+
+    /// Asserts that the request with the given index (starting at 0) matches.
+    #[track_caller]
+    pub fn assert_request(&self, index: usize, matcher: RequestMatcher) {
+        let state = self.state.borrow();
+        let Some(request) = state.requests.get(index) else {
+            panic!(
+                "expected request #{index}, but only {} requests were received",
+                state.requests.len()
+            );
+        };
+        let mismatches = matcher.mismatches(request);
+        assert!(
+            mismatches.is_empty(),
+            "request #{index} does not satisfy: {}\nrequest: {request:#?}",
+            mismatches.join(", ")
+        );
+    }
+
+    /// Asserts that every scripted reply has been used.
+    #[track_caller]
+    pub fn assert_done(&self) {
+        let remaining = self.remaining_replies();
+        assert_eq!(remaining, 0, "{remaining} scripted replies were not used");
+    }
+}
+
+impl LlmTransport for MockLlm {
+    fn chat(&self, request: Request) -> TransportFuture<'_> {
+        let mut state = self.state.borrow_mut();
+        let index = state.requests.len();
+        let position = state.replies.iter().position(|reply| {
+            reply
+                .matcher
+                .as_ref()
+                .is_none_or(|matcher| matcher.matches(&request))
+        });
+        let Some(reply) = position.and_then(|position| state.replies.remove(position)) else {
+            let mismatches: Vec<_> = state
+                .replies
+                .iter()
+                .filter_map(|reply| reply.matcher.as_ref())
+                .map(|matcher| matcher.mismatches(&request).join(", "))
+                .collect();
+            if mismatches.is_empty() {
+                panic!("unexpected request #{index}: no replies left\nrequest: {request:#?}");
+            }
+            panic!(
+                "request #{index} matches no scripted reply, which require: {}\nrequest: {request:#?}",
+                mismatches.join("; ")
+            );
+        };
+        state.requests.push(request);
+        Box::pin(async move { reply.result })
+    }
+
+    fn wait(&self, delay: Duration) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        self.state.borrow_mut().waits.push(delay);
+        Box::pin(async {})
+    }
+}
+
+/// Creates a response with the given text content.
+pub fn text_response<S: Into<String>>(content: S) -> Response {
+    Response {
+        message: AssistantMessage {
+            content: Some(content.into()),
+            tool_calls: vec![],
+        },
+        ..Default::default()
+    }
+}
+
+/// Creates a response that calls the given tools.
+pub fn tool_calls_response(tool_calls: Vec<ToolCall>) -> Response {
+    Response {
+        message: AssistantMessage {
+            content: None,
+            tool_calls,
+        },
+        ..Default::default()
+    }
+}
+
+/// Creates a call of the tool `name` with the given arguments.
+pub fn tool_call(id: &str, name: &str, arguments: &[(&str, &str)]) -> ToolCall {
+    ToolCall {
+        id: id.to_string(),
+        function: FunctionCall {
+            name: name.to_string(),
+            arguments: arguments
+                .iter()
+                .map(|(name, value)| ToolCallArgument {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+        },
+    }
+}
+
+/// Runs a future to completion, panicking if it has to wait.
+///
+/// Requests served by a [`MockLlm`] complete immediately, so this is enough to
+/// drive agent code in tests without an async runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("future is not ready"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatBuilder, Model, ToolBuilder};
+
+    fn send(mock: &MockLlm, messages: Vec<ChatMessage>) -> Result<Response, LlmError> {
+        block_on(
+            ChatBuilder::new(Model::Llama3_1_8B)
+                .with_messages(messages)
+                .with_tools(vec![ToolBuilder::new("get_weather").build()])
+                .with_transport(mock.clone())
+                .try_send(),
+        )
+    }
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage::User {
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn replays_responses_in_order() {
+        let mock = MockLlm::new();
+        mock.reply(text_response("first"))
+            .fail(LlmError::Transient("busy".to_string()));
+
+        let response = send(&mock, vec![user("hi")]).unwrap();
+        assert_eq!(response.message.content, Some("first".to_string()));
+        assert_eq!(
+            send(&mock, vec![user("hi again")]).unwrap_err(),
+            LlmError::Transient("busy".to_string())
+        );
+        assert_eq!(mock.requests().len(), 2);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn records_and_asserts_requests() {
+        let mock = MockLlm::new();
+        mock.reply(tool_calls_response(vec![tool_call(
+            "call_1",
+            "get_weather",
+            &[("location", "Cairo")],
+        )]))
+        .reply(text_response("Sunny"));
+
+        let first = send(&mock, vec![user("Weather in Cairo?")]).unwrap();
+        send(
+            &mock,
+            vec![
+                user("Weather in Cairo?"),
+                ChatMessage::Assistant(first.message),
+                ChatMessage::Tool {
+                    content: "25°C".to_string(),
+                    tool_call_id: "call_1".to_string(),
+                },
+            ],
+        )
+        .unwrap();
+
+        mock.assert_request(
+            0,
+            RequestMatcher::new()
+                .with_model("llama3.1:8b")
+                .with_tool("get_weather")
+                .with_last_user_message_containing("Cairo"),
+        );
+        mock.assert_request(1, RequestMatcher::new().with_tool_result("call_1"));
+    }
+
+    #[test]
+    fn selects_the_first_matching_reply() {
+        let mock = MockLlm::new();
+        mock.reply_when(
+            RequestMatcher::new().with_message_containing("Rome"),
+            text_response("Rainy"),
+        )
+        .reply_when(
+            RequestMatcher::new().with_message_containing("Cairo"),
+            text_response("Sunny"),
+        )
+        .reply(text_response("Where?"));
+
+        let content = |messages| send(&mock, messages).unwrap().message.content;
+        assert_eq!(content(vec![user("Weather?")]), Some("Where?".to_string()));
+        assert_eq!(content(vec![user("In Cairo")]), Some("Sunny".to_string()));
+        assert_eq!(content(vec![user("In Rome")]), Some("Rainy".to_string()));
+        mock.assert_done();
+    }
+
+    #[test]
+    #[should_panic(expected = "a tool message answers call \"call_2\"")]
+    fn panics_when_request_does_not_match_reply() {
+        let mock = MockLlm::new();
+        mock.reply_when(
+            RequestMatcher::new().with_tool_result("call_2"),
+            text_response("Sunny"),
+        );
+        let _ = send(&mock, vec![user("hi")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no replies left")]
+    fn panics_on_unexpected_request() {
+        let _ = send(&MockLlm::new(), vec![user("hi")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "only 0 requests were received")]
+    fn assert_request_panics_for_missing_request() {
+        MockLlm::new().assert_request(0, RequestMatcher::new());
+    }
+}
@@ -1,5 +1,8 @@
+use crate::chat::AssistantMessage;
+use candid::types::{Serializer, Type, TypeInner};
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(CandidType, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum Tool {
@@ -26,7 +29,7 @@ pub struct Function {
     pub parameters: Option<Parameters>,
 }
 
-#[derive(CandidType, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(CandidType, Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
 pub struct Property {
     #[serde(rename = "type")]
     pub type_: String,
@@ -35,6 +38,151 @@ pub struct Property {
     pub description: Option<String>,
     #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
     pub enum_: Option<Vec<String>>,
+    /// The schema of the elements of an `array` property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Items>>,
+    /// The fields of an `object` property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Vec<Property>>,
+    /// The required fields of an `object` property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    /// The smallest allowed value of a `number` or `integer` property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<Bound>,
+    /// The largest allowed value of a `number` or `integer` property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<Bound>,
+    /// The minimum number of characters of a `string` property.
+    #[serde(default, rename = "minLength", skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<u64>,
+    /// The maximum number of characters of a `string` property.
+    #[serde(default, rename = "maxLength", skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<u64>,
+    /// A regular expression that the value of a `string` property must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// The value used when the argument is omitted, in the same form as the
+    /// values of tool call arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Whether the argument may be `null`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nullable: Option<bool>,
+}
+
+/// A bound of a numeric property, as set with [`ParameterBuilder::with_minimum`]
+/// and [`ParameterBuilder::with_maximum`].
+///
+/// Bounds compare equal if their values have the same bits, so that properties
+/// can be compared with `Eq`. They are encoded as plain numbers.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct Bound(pub f64);
+
+impl PartialEq for Bound {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Bound {}
+
+impl From<f64> for Bound {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl CandidType for Bound {
+    fn _ty() -> Type {
+        TypeInner::Float64.into()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        serializer.serialize_float64(self.0)
+    }
+}
+
+/// The schema of the elements of an array.
+#[derive(CandidType, Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Items {
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The schema of the elements of nested arrays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Items>>,
+    /// The fields of `object` elements.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Vec<Property>>,
+    /// The required fields of `object` elements.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+}
+
+/// Controls whether and which tools the model calls.
+#[derive(CandidType, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolChoice {
+    /// The model decides whether to call tools. This is the default.
+    Auto,
+    /// The model must not call any tools, even though they are visible.
+    None,
+    /// The model must call at least one tool.
+    Required,
+    /// The model must call the function with the given name.
+    Function(String),
+}
+
+impl ToolChoice {
+    /// Creates a choice that forces a call to the function with the given name.
+    pub fn function<S: Into<String>>(name: S) -> Self {
+        ToolChoice::Function(name.into())
+    }
+
+    /// Returns whether the model can comply with this choice given the offered tools.
+    pub(crate) fn is_satisfiable_with(&self, tools: &[Tool]) -> bool {
+        match self {
+            ToolChoice::Auto | ToolChoice::None => true,
+            ToolChoice::Required => !tools.is_empty(),
+            ToolChoice::Function(name) => tools
+                .iter()
+                .any(|Tool::Function(function)| &function.name == name),
+        }
+    }
+
+    /// Returns whether the tool calls of `message` comply with this choice.
+    pub(crate) fn is_satisfied_by(&self, message: &AssistantMessage) -> bool {
+        let calls = &message.tool_calls;
+        match self {
+            ToolChoice::Auto => true,
+            ToolChoice::None => calls.is_empty(),
+            ToolChoice::Required => !calls.is_empty(),
+            ToolChoice::Function(name) => {
+                !calls.is_empty() && calls.iter().all(|call| &call.function.name == name)
+            }
+        }
+    }
+
+    /// Returns the instruction that reminds the model of this choice.
+    pub(crate) fn instruction(&self) -> Option<String> {
+        match self {
+            ToolChoice::Auto => None,
+            ToolChoice::None => {
+                Some("Do not call any tools. Answer the user directly.".to_string())
+            }
+            ToolChoice::Required => Some("You must call one of the available tools.".to_string()),
+            ToolChoice::Function(name) => Some(format!(
+                "You must call the `{name}` tool, and no other tool."
+            )),
+        }
+    }
 }
 
 /// Enum representing the types a parameter can have.
@@ -43,20 +191,87 @@ pub enum ParameterType {
     String,
     Boolean,
     Number,
-    // Can be extended with more types as needed
+    Integer,
+    /// An array whose elements have the given type.
+    ///
+    /// The current LLM canister ignores the type of the elements, so the model
+    /// only sees that the argument is an array.
+    Array(Box<ParameterType>),
+    /// An object with the given fields.
+    ///
+    /// The current LLM canister ignores the fields, so the model only sees that the
+    /// argument is an object.
+    Object(Vec<ParameterBuilder>),
 }
 
 impl ParameterType {
+    /// Creates an array type whose elements have the given type.
+    pub fn array_of(items: ParameterType) -> Self {
+        ParameterType::Array(Box::new(items))
+    }
+
     fn as_str(&self) -> &'static str {
         match self {
             ParameterType::String => "string",
             ParameterType::Boolean => "boolean",
             ParameterType::Number => "number",
+            ParameterType::Integer => "integer",
+            ParameterType::Array(_) => "array",
+            ParameterType::Object(_) => "object",
+        }
+    }
+
+    /// The schema of the elements of an array type.
+    fn items(&self) -> Option<Box<Items>> {
+        match self {
+            ParameterType::Array(items) => Some(Box::new(Items {
+                type_: items.as_str().to_string(),
+                items: items.items(),
+                properties: items.properties(),
+                required: items.required(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// The fields of an object type.
+    fn properties(&self) -> Option<Vec<Property>> {
+        match self {
+            ParameterType::Object(fields) => Some(fields.iter().map(|f| f.to_property()).collect()),
+            _ => None,
         }
     }
+
+    /// The required fields of an object type.
+    fn required(&self) -> Option<Vec<String>> {
+        match self {
+            ParameterType::Object(fields) => required_names(fields),
+            _ => None,
+        }
+    }
+}
+
+/// The names of the required parameters, or `None` if there are none.
+fn required_names(parameters: &[ParameterBuilder]) -> Option<Vec<String>> {
+    let required = parameters
+        .iter()
+        .filter(|p| p.required)
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>();
+    if required.is_empty() {
+        None
+    } else {
+        Some(required)
+    }
 }
 
 /// Builder for creating a parameter for a function tool.
+///
+/// The constraints set with `with_minimum`, `with_maximum`, `with_min_length`,
+/// `with_max_length`, `with_pattern`, `with_default` and `is_nullable` are ignored
+/// by the current LLM canister, whose interface only accepts the type, description
+/// and allowed values of a parameter, so the model doesn't see them. They are still
+/// used by [`validate_tool_calls`](crate::validate_tool_calls) and structured output.
 #[derive(Clone, Debug)]
 pub struct ParameterBuilder {
     name: String,
@@ -64,6 +279,13 @@ pub struct ParameterBuilder {
     description: Option<String>,
     required: bool,
     enum_values: Option<Vec<String>>,
+    minimum: Option<Bound>,
+    maximum: Option<Bound>,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    pattern: Option<String>,
+    default: Option<String>,
+    nullable: bool,
 }
 
 impl ParameterBuilder {
@@ -75,6 +297,13 @@ impl ParameterBuilder {
             description: None,
             required: false,
             enum_values: None,
+            minimum: None,
+            maximum: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            default: None,
+            nullable: false,
         }
     }
 
@@ -99,6 +328,50 @@ impl ParameterBuilder {
         self
     }
 
+    /// Set the smallest allowed value of a numeric parameter.
+    pub fn with_minimum(mut self, minimum: f64) -> Self {
+        self.minimum = Some(Bound(minimum));
+        self
+    }
+
+    /// Set the largest allowed value of a numeric parameter.
+    pub fn with_maximum(mut self, maximum: f64) -> Self {
+        self.maximum = Some(Bound(maximum));
+        self
+    }
+
+    /// Set the minimum number of characters of a string parameter.
+    pub fn with_min_length(mut self, min_length: u64) -> Self {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    /// Set the maximum number of characters of a string parameter.
+    pub fn with_max_length(mut self, max_length: u64) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Set a regular expression that the value of a string parameter must match.
+    ///
+    /// If the pattern isn't a valid regular expression, no value matches it.
+    pub fn with_pattern<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    /// Set the value used when the argument is omitted.
+    pub fn with_default<S: Into<String>>(mut self, default: S) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    /// Allow the argument to be `null`.
+    pub fn is_nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
     /// Convert the builder to a Property.
     fn to_property(&self) -> Property {
         Property {
@@ -106,6 +379,16 @@ impl ParameterBuilder {
             name: self.name.clone(),
             description: self.description.clone(),
             enum_: self.enum_values.clone(),
+            items: self.type_.items(),
+            properties: self.type_.properties(),
+            required: self.type_.required(),
+            minimum: self.minimum,
+            maximum: self.maximum,
+            min_length: self.min_length,
+            max_length: self.max_length,
+            pattern: self.pattern.clone(),
+            default: self.default.clone(),
+            nullable: self.nullable.then_some(true),
         }
     }
 }
@@ -141,32 +424,45 @@ impl ToolBuilder {
         self
     }
 
+    /// Sets the parameters of the function from the JSON schema of `T`, typically
+    /// the struct that the arguments are deserialized into with
+    /// [`FunctionCall::parse`](crate::FunctionCall::parse).
+    ///
+    /// Field doc comments become descriptions, nested structs become object
+    /// parameters, unit enums become enum values and `Option` fields are not
+    /// required. Parameters added with [`with_parameter`](Self::with_parameter)
+    /// are appended to these.
+    ///
+    /// Fails if the schema of `T` is not an object, or uses constructs that cannot
+    /// be represented as parameters, such as untagged enums.
+    #[cfg(feature = "schemars")]
+    pub fn with_parameters_from<T: schemars::JsonSchema>(
+        mut self,
+    ) -> Result<Self, crate::SchemaError> {
+        self.function.parameters = Some(crate::json_schema::parameters_from::<T>()?);
+        Ok(self)
+    }
+
     /// Builds the final Tool.
     pub fn build(self) -> Tool {
         let mut function = self.function;
 
         if !self.parameters.is_empty() {
-            let properties = self
-                .parameters
-                .iter()
-                .map(|p| p.to_property())
-                .collect::<Vec<_>>();
-            let required = self
-                .parameters
-                .iter()
-                .filter(|p| p.required)
-                .map(|p| p.name.clone())
-                .collect::<Vec<_>>();
-
-            function.parameters = Some(Parameters {
+            let parameters = function.parameters.get_or_insert_with(|| Parameters {
                 type_: "object".to_string(),
-                properties: Some(properties),
-                required: if required.is_empty() {
-                    None
-                } else {
-                    Some(required)
-                },
+                properties: None,
+                required: None,
             });
+            parameters
+                .properties
+                .get_or_insert_with(Vec::new)
+                .extend(self.parameters.iter().map(|p| p.to_property()));
+            if let Some(required) = required_names(&self.parameters) {
+                parameters
+                    .required
+                    .get_or_insert_with(Vec::new)
+                    .extend(required);
+            }
         }
 
         Tool::Function(function)
@@ -225,6 +521,7 @@ mod tests {
                     type_: "string".to_string(),
                     description: Some("Test parameter".to_string()),
                     enum_: None,
+                    ..Default::default()
                 }]),
                 required: Some(vec!["param1".to_string()]),
             }),
@@ -263,18 +560,21 @@ mod tests {
                         type_: "string".to_string(),
                         description: Some("City name".to_string()),
                         enum_: None,
+                        ..Default::default()
                     },
                     Property {
                         name: "units".to_string(),
                         type_: "string".to_string(),
                         description: Some("Temperature units".to_string()),
                         enum_: None,
+                        ..Default::default()
                     },
                     Property {
                         name: "forecast".to_string(),
                         type_: "boolean".to_string(),
                         description: Some("Include forecast".to_string()),
                         enum_: None,
+                        ..Default::default()
                     },
                 ]),
                 required: Some(vec!["location".to_string()]),
@@ -303,6 +603,7 @@ mod tests {
                     type_: "string".to_string(),
                     description: Some("This parameter is optional".to_string()),
                     enum_: None,
+                    ..Default::default()
                 }]),
                 required: None,
             }),
@@ -316,6 +617,12 @@ mod tests {
         assert_eq!(ParameterType::String.as_str(), "string");
         assert_eq!(ParameterType::Boolean.as_str(), "boolean");
         assert_eq!(ParameterType::Number.as_str(), "number");
+        assert_eq!(ParameterType::Integer.as_str(), "integer");
+        assert_eq!(
+            ParameterType::array_of(ParameterType::String).as_str(),
+            "array"
+        );
+        assert_eq!(ParameterType::Object(vec![]).as_str(), "object");
     }
 
     #[test]
@@ -342,6 +649,7 @@ mod tests {
                         "The location to get the weather for (e.g. Cairo, Egypt)".to_string(),
                     ),
                     enum_: None,
+                    ..Default::default()
                 }]),
                 required: Some(vec!["location".to_string()]),
             }),
@@ -371,6 +679,7 @@ mod tests {
                     type_: "number".to_string(),
                     description: Some("The numeric value to use in calculation".to_string()),
                     enum_: None,
+                    ..Default::default()
                 }]),
                 required: Some(vec!["value".to_string()]),
             }),
@@ -407,6 +716,7 @@ mod tests {
                         type_: "number".to_string(),
                         description: Some("The value to convert".to_string()),
                         enum_: None,
+                        ..Default::default()
                     },
                     Property {
                         name: "unit".to_string(),
@@ -418,6 +728,7 @@ mod tests {
                             "kilometers".to_string(),
                             "miles".to_string(),
                         ]),
+                        ..Default::default()
                     },
                 ]),
                 required: Some(vec!["value".to_string(), "unit".to_string()]),
@@ -426,4 +737,166 @@ mod tests {
 
         assert_eq!(tool, expected);
     }
+
+    #[test]
+    fn nested_parameters() {
+        let tool = ToolBuilder::new("find_transactions")
+            .with_parameter(
+                ParameterBuilder::new("accounts", ParameterType::array_of(ParameterType::String))
+                    .is_required(),
+            )
+            .with_parameter(ParameterBuilder::new(
+                "filter",
+                ParameterType::Object(vec![
+                    ParameterBuilder::new("min_amount", ParameterType::Integer).is_required(),
+                    ParameterBuilder::new(
+                        "ranges",
+                        ParameterType::array_of(ParameterType::Object(vec![
+                            ParameterBuilder::new("from", ParameterType::Integer).is_required(),
+                            ParameterBuilder::new("to", ParameterType::Integer),
+                        ])),
+                    ),
+                ]),
+            ))
+            .build();
+
+        let integer = |name: &str| Property {
+            type_: "integer".to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let expected = Tool::Function(Function {
+            name: "find_transactions".to_string(),
+            description: None,
+            parameters: Some(Parameters {
+                type_: "object".to_string(),
+                properties: Some(vec![
+                    Property {
+                        type_: "array".to_string(),
+                        name: "accounts".to_string(),
+                        items: Some(Box::new(Items {
+                            type_: "string".to_string(),
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    },
+                    Property {
+                        type_: "object".to_string(),
+                        name: "filter".to_string(),
+                        properties: Some(vec![
+                            integer("min_amount"),
+                            Property {
+                                type_: "array".to_string(),
+                                name: "ranges".to_string(),
+                                items: Some(Box::new(Items {
+                                    type_: "object".to_string(),
+                                    items: None,
+                                    properties: Some(vec![integer("from"), integer("to")]),
+                                    required: Some(vec!["from".to_string()]),
+                                })),
+                                ..Default::default()
+                            },
+                        ]),
+                        required: Some(vec!["min_amount".to_string()]),
+                        ..Default::default()
+                    },
+                ]),
+                required: Some(vec!["accounts".to_string()]),
+            }),
+        });
+
+        assert_eq!(tool, expected);
+    }
+
+    #[test]
+    fn nested_parameters_survive_encoding() {
+        let tool = ToolBuilder::new("find_transactions")
+            .with_parameter(ParameterBuilder::new(
+                "filter",
+                ParameterType::Object(vec![ParameterBuilder::new(
+                    "accounts",
+                    ParameterType::array_of(ParameterType::array_of(ParameterType::Integer)),
+                )
+                .is_required()]),
+            ))
+            .build();
+
+        let bytes = candid::encode_one(&tool).unwrap();
+        assert_eq!(candid::decode_one::<Tool>(&bytes).unwrap(), tool);
+
+        let json = serde_json::to_string(&tool).unwrap();
+        assert_eq!(
+            json,
+            r#"{"function":{"name":"find_transactions","parameters":{"type":"object","properties":[{"type":"object","name":"filter","properties":[{"type":"array","name":"accounts","items":{"type":"array","items":{"type":"integer"}}}],"required":["accounts"]}]}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Tool>(&json).unwrap(), tool);
+    }
+
+    #[test]
+    fn parameter_constraints() {
+        let tool = ToolBuilder::new("lookup_account")
+            .with_parameter(
+                ParameterBuilder::new("account", ParameterType::String)
+                    .with_min_length(64)
+                    .with_max_length(64)
+                    .with_pattern("^[0-9a-f]*$")
+                    .is_required(),
+            )
+            .with_parameter(
+                ParameterBuilder::new("decimals", ParameterType::Integer)
+                    .with_minimum(0.0)
+                    .with_maximum(8.0)
+                    .with_default("2"),
+            )
+            .with_parameter(ParameterBuilder::new("memo", ParameterType::String).is_nullable())
+            .build();
+
+        let Tool::Function(function) = &tool;
+        assert_eq!(
+            function.parameters.as_ref().unwrap().properties,
+            Some(vec![
+                Property {
+                    type_: "string".to_string(),
+                    name: "account".to_string(),
+                    min_length: Some(64),
+                    max_length: Some(64),
+                    pattern: Some("^[0-9a-f]*$".to_string()),
+                    ..Default::default()
+                },
+                Property {
+                    type_: "integer".to_string(),
+                    name: "decimals".to_string(),
+                    minimum: Some(Bound(0.0)),
+                    maximum: Some(Bound(8.0)),
+                    default: Some("2".to_string()),
+                    ..Default::default()
+                },
+                Property {
+                    type_: "string".to_string(),
+                    name: "memo".to_string(),
+                    nullable: Some(true),
+                    ..Default::default()
+                },
+            ])
+        );
+
+        let json = serde_json::to_value(&tool).unwrap();
+        assert_eq!(
+            json["function"]["parameters"]["properties"][0],
+            serde_json::json!({
+                "type": "string",
+                "name": "account",
+                "minLength": 64,
+                "maxLength": 64,
+                "pattern": "^[0-9a-f]*$",
+            })
+        );
+        assert_eq!(
+            json["function"]["parameters"]["properties"][1]["maximum"],
+            8.0
+        );
+        assert_eq!(serde_json::from_value::<Tool>(json).unwrap(), tool);
+        let bytes = candid::encode_one(&tool).unwrap();
+        assert_eq!(candid::decode_one::<Tool>(&bytes).unwrap(), tool);
+    }
 }
@@ -0,0 +1,165 @@
+use crate::chat::{Request, Response};
+use crate::error::LlmError;
+use crate::retry;
+use crate::size;
+use candid::Principal;
+use ic_cdk::call::Call;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The future returned by [`LlmTransport::chat`].
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Response, LlmError>> + 'a>>;
+
+/// A backend that chat requests are dispatched to.
+///
+/// By default, requests are sent to the LLM canister with an inter-canister call
+/// (see [`CanisterTransport`]). A different transport can be set for a single
+/// request with [`ChatBuilder::with_transport`](crate::ChatBuilder::with_transport)
+/// or for all requests with [`set_transport`], e.g. to test agent logic without
+/// deploying a canister.
+pub trait LlmTransport {
+    /// Sends a chat request and returns the response of the model.
+    fn chat(&self, request: Request) -> TransportFuture<'_>;
+
+    /// Waits for at least `delay` before a failed request is retried, see
+    /// [`RetryPolicy::with_delay`](crate::RetryPolicy::with_delay).
+    ///
+    /// By default, the management canister's `raw_rand` is called until the delay
+    /// has passed. Unlike a timer, the outstanding calls keep the current message
+    /// open, so that the retry is made before the message answers its caller.
+    fn wait(&self, delay: Duration) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        Box::pin(retry::wait_with_calls(delay))
+    }
+}
+
+impl<T: LlmTransport + ?Sized> LlmTransport for Rc<T> {
+    fn chat(&self, request: Request) -> TransportFuture<'_> {
+        (**self).chat(request)
+    }
+
+    fn wait(&self, delay: Duration) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        (**self).wait(delay)
+    }
+}
+
+/// The number of seconds that bounded-wait calls wait for a response by default.
+pub(crate) const DEFAULT_TIMEOUT_SECS: u32 = 300;
+
+/// How long a call to the LLM canister waits for its response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitMode {
+    /// The call gives up after a timeout, failing with [`LlmError::Timeout`]. The LLM
+    /// canister may still process the request, so its outcome is unknown.
+    #[default]
+    Bounded,
+    /// The call waits until the response arrives, however long that takes. While it
+    /// waits, the calling canister cannot be stopped, and thus not upgraded.
+    Unbounded,
+}
+
+/// Sends chat requests to the LLM canister with an inter-canister call.
+#[derive(Clone, Debug)]
+pub struct CanisterTransport {
+    canister: Principal,
+    timeout_secs: u32,
+    wait_mode: WaitMode,
+    tool_content_limit: Option<usize>,
+}
+
+impl CanisterTransport {
+    /// Creates a transport that calls the given LLM canister.
+    pub fn new(canister: Principal) -> Self {
+        Self {
+            canister,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            wait_mode: WaitMode::Bounded,
+            tool_content_limit: None,
+        }
+    }
+
+    /// Sets how many seconds bounded-wait calls wait for a response. Defaults to 300.
+    pub fn with_timeout(mut self, timeout_secs: u32) -> Self {
+        self.timeout_secs = timeout_secs;
+        self
+    }
+
+    /// Sets how long calls wait for their response. Defaults to [`WaitMode::Bounded`].
+    pub fn with_wait_mode(mut self, wait_mode: WaitMode) -> Self {
+        self.wait_mode = wait_mode;
+        self
+    }
+
+    /// Truncates the contents of tool messages to `max_bytes` if a request would
+    /// otherwise exceed the size limit of inter-canister messages. See
+    /// [`ChatBuilder::with_tool_content_limit`](crate::ChatBuilder::with_tool_content_limit).
+    pub fn with_tool_content_limit(mut self, max_bytes: usize) -> Self {
+        self.tool_content_limit = Some(max_bytes);
+        self
+    }
+}
+
+impl LlmTransport for CanisterTransport {
+    fn chat(&self, mut request: Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let args = size::encode(&mut request, self.tool_content_limit)?;
+
+            let call = match self.wait_mode {
+                WaitMode::Bounded => {
+                    Call::bounded_wait(self.canister, "v1_chat").change_timeout(self.timeout_secs)
+                }
+                WaitMode::Unbounded => Call::unbounded_wait(self.canister, "v1_chat"),
+            };
+            call.take_raw_args(args)
+                .await?
+                .candid()
+                .map_err(|e| LlmError::Decode(e.to_string()))
+        })
+    }
+}
+
+/// A transport shared between builders.
+#[derive(Clone)]
+pub(crate) struct SharedTransport(Rc<dyn LlmTransport>);
+
+impl SharedTransport {
+    pub(crate) fn new<T: LlmTransport + 'static>(transport: T) -> Self {
+        Self(Rc::new(transport))
+    }
+
+    pub(crate) fn chat(&self, request: Request) -> TransportFuture<'_> {
+        self.0.chat(request)
+    }
+
+    pub(crate) fn wait(&self, delay: Duration) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        self.0.wait(delay)
+    }
+}
+
+impl fmt::Debug for SharedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedTransport")
+    }
+}
+
+thread_local! {
+    static GLOBAL_TRANSPORT: RefCell<Option<SharedTransport>> = const { RefCell::new(None) };
+}
+
+/// Sets the transport used by all chat requests that don't set their own.
+pub fn set_transport<T: LlmTransport + 'static>(transport: T) {
+    GLOBAL_TRANSPORT.with_borrow_mut(|global| *global = Some(SharedTransport::new(transport)));
+}
+
+/// Removes the transport set with [`set_transport`], so that chat requests are
+/// sent to the LLM canister again.
+pub fn clear_transport() {
+    GLOBAL_TRANSPORT.with_borrow_mut(|global| *global = None);
+}
+
+pub(crate) fn global_transport() -> Option<SharedTransport> {
+    GLOBAL_TRANSPORT.with_borrow(|global| global.clone())
+}
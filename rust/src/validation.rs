@@ -0,0 +1,633 @@
+use crate::chat::{AssistantMessage, ToolCall};
+use crate::structured;
+use crate::tool::{Property, Tool};
+use regex_lite::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A way in which a tool call doesn't match the schema of the tool it calls.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// No tool with the called name was offered to the model.
+    UnknownFunction,
+    /// A required argument is missing.
+    MissingArgument(String),
+    /// An argument that the tool doesn't declare.
+    UnexpectedArgument(String),
+    /// The value of an argument isn't one of its allowed values.
+    NotInEnum {
+        argument: String,
+        value: String,
+        allowed: Vec<String>,
+    },
+    /// The value of an argument doesn't match its declared type.
+    TypeMismatch {
+        argument: String,
+        value: String,
+        expected: String,
+    },
+    /// The value of an argument violates a constraint of its property, such as
+    /// a range, a length or a pattern.
+    ConstraintViolated {
+        argument: String,
+        value: String,
+        constraint: String,
+    },
+    /// The elements or fields of an `array` or `object` argument don't match the
+    /// schema of its items or fields.
+    InvalidNestedValue {
+        argument: String,
+        value: String,
+        errors: Vec<String>,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::UnknownFunction => write!(f, "unknown function"),
+            Violation::MissingArgument(name) => write!(f, "missing required argument `{name}`"),
+            Violation::UnexpectedArgument(name) => write!(f, "unexpected argument `{name}`"),
+            Violation::NotInEnum {
+                argument,
+                value,
+                allowed,
+            } => write!(
+                f,
+                "argument `{argument}` is {value:?}, expected one of: {}",
+                allowed.join(", ")
+            ),
+            Violation::TypeMismatch {
+                argument,
+                value,
+                expected,
+            } => write!(
+                f,
+                "argument `{argument}` is {value:?}, expected a {expected}"
+            ),
+            Violation::ConstraintViolated {
+                argument,
+                value,
+                constraint,
+            } => write!(
+                f,
+                "argument `{argument}` is {value:?}, expected {constraint}"
+            ),
+            Violation::InvalidNestedValue {
+                argument,
+                value,
+                errors,
+            } => write!(
+                f,
+                "argument `{argument}` is {value:?}: {}",
+                errors.join("; ")
+            ),
+        }
+    }
+}
+
+/// A tool call that doesn't match the schema of the tool it calls.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidToolCall {
+    /// The id of the tool call.
+    pub tool_call_id: String,
+    /// The name of the called function.
+    pub function: String,
+    /// Everything that is wrong with the call.
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for InvalidToolCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid call to `{}`: ", self.function)?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidToolCall {}
+
+/// Checks a tool call against the tools that were sent with the request.
+///
+/// All violations are reported, so that they can be passed back to the model at once.
+pub fn validate_tool_call(tools: &[Tool], tool_call: &ToolCall) -> Result<(), InvalidToolCall> {
+    let call = &tool_call.function;
+    let function = tools.iter().find_map(|tool| match tool {
+        Tool::Function(function) if function.name == call.name => Some(function),
+        _ => None,
+    });
+
+    let mut violations = Vec::new();
+    match function {
+        None => violations.push(Violation::UnknownFunction),
+        Some(function) => {
+            let parameters = function.parameters.as_ref();
+            let properties = parameters
+                .and_then(|p| p.properties.as_deref())
+                .unwrap_or_default();
+            let required = parameters
+                .and_then(|p| p.required.as_deref())
+                .unwrap_or_default();
+
+            for name in required {
+                if !call.arguments.iter().any(|arg| &arg.name == name) {
+                    violations.push(Violation::MissingArgument(name.clone()));
+                }
+            }
+            for argument in &call.arguments {
+                match properties.iter().find(|p| p.name == argument.name) {
+                    Some(property) => {
+                        violations.extend(check_value(property, &argument.value));
+                    }
+                    None => violations.push(Violation::UnexpectedArgument(argument.name.clone())),
+                }
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidToolCall {
+            tool_call_id: tool_call.id.clone(),
+            function: call.name.clone(),
+            violations,
+        })
+    }
+}
+
+/// Checks all the tool calls of a message against the tools that were sent with the request.
+///
+/// # Example
+///
+/// ```
+/// use ic_llm::{AssistantMessage, ChatMessage, ToolRegistry};
+///
+/// async fn tool_results(registry: &ToolRegistry, message: &AssistantMessage) -> Vec<ChatMessage> {
+///     match ic_llm::validate_tool_calls(&registry.tools(), message) {
+///         Ok(()) => {
+///             let mut results = Vec::new();
+///             for tool_call in &message.tool_calls {
+///                 results.push(registry.execute(tool_call).await);
+///             }
+///             results
+///         }
+///         // Let the model correct its calls.
+///         Err(invalid) => invalid
+///             .into_iter()
+///             .map(|call| ChatMessage::Tool {
+///                 content: format!("Error: {call}"),
+///                 tool_call_id: call.tool_call_id,
+///             })
+///             .collect(),
+///     }
+/// }
+/// ```
+pub fn validate_tool_calls(
+    tools: &[Tool],
+    message: &AssistantMessage,
+) -> Result<(), Vec<InvalidToolCall>> {
+    let invalid: Vec<_> = message
+        .tool_calls
+        .iter()
+        .filter_map(|tool_call| validate_tool_call(tools, tool_call).err())
+        .collect();
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid)
+    }
+}
+
+/// Checks a value against the type, allowed values and constraints of a property,
+/// and the value of an `array` or `object` against the schema of its items or fields.
+fn check_value(property: &Property, value: &str) -> Option<Violation> {
+    let trimmed = value.trim();
+    if property.nullable == Some(true) && trimmed == "null" {
+        return None;
+    }
+
+    let mut json = None;
+    let valid_type = match property.type_.as_str() {
+        "number" => trimmed.parse::<f64>().is_ok_and(f64::is_finite),
+        "integer" => trimmed.parse::<i128>().is_ok(),
+        "boolean" => trimmed == "true" || trimmed == "false",
+        "array" | "object" => {
+            json = serde_json::from_str::<serde_json::Value>(trimmed).ok();
+            json.as_ref().is_some_and(|json| {
+                json.is_array() && property.type_ == "array"
+                    || json.is_object() && property.type_ == "object"
+            })
+        }
+        _ => true,
+    };
+    if !valid_type {
+        return Some(Violation::TypeMismatch {
+            argument: property.name.clone(),
+            value: value.to_string(),
+            expected: property.type_.clone(),
+        });
+    }
+
+    if let Some(allowed) = &property.enum_ {
+        if !allowed.iter().any(|a| a == value) {
+            return Some(Violation::NotInEnum {
+                argument: property.name.clone(),
+                value: value.to_string(),
+                allowed: allowed.clone(),
+            });
+        }
+    }
+
+    if let Some(constraint) = unmet_constraint(property, value) {
+        return Some(Violation::ConstraintViolated {
+            argument: property.name.clone(),
+            value: value.to_string(),
+            constraint,
+        });
+    }
+
+    // The items and fields are checked like structured output.
+    let errors = structured::check_nested(property, &mut json?);
+    (!errors.is_empty()).then(|| Violation::InvalidNestedValue {
+        argument: property.name.clone(),
+        value: value.to_string(),
+        errors,
+    })
+}
+
+/// Returns a description of the first constraint of `property` that `value` doesn't meet.
+///
+/// Numeric bounds only apply to `number` and `integer` properties, and lengths and
+/// patterns only to `string` properties.
+pub(crate) fn unmet_constraint(property: &Property, value: &str) -> Option<String> {
+    match property.type_.as_str() {
+        "number" | "integer" => {
+            let number = value.trim().parse::<f64>().ok()?;
+            if let Some(minimum) = property.minimum.filter(|minimum| number < minimum.0) {
+                return Some(format!("at least {minimum}"));
+            }
+            if let Some(maximum) = property.maximum.filter(|maximum| number > maximum.0) {
+                return Some(format!("at most {maximum}"));
+            }
+            None
+        }
+        "string" => {
+            let length = value.chars().count() as u64;
+            if let Some(min_length) = property.min_length.filter(|&min| length < min) {
+                return Some(format!("at least {min_length} characters"));
+            }
+            if let Some(max_length) = property.max_length.filter(|&max| length > max) {
+                return Some(format!("at most {max_length} characters"));
+            }
+            let pattern = property.pattern.as_ref()?;
+            match matches_pattern(pattern, value) {
+                Some(true) => None,
+                Some(false) => Some(format!("a match for `{pattern}`")),
+                None => Some(format!(
+                    "a match for `{pattern}`, which isn't a valid regular expression"
+                )),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The maximum number of compiled patterns that are kept.
+const MAX_CACHED_PATTERNS: usize = 64;
+
+thread_local! {
+    /// The compiled patterns of tool parameters, which are checked on every call.
+    static PATTERNS: RefCell<HashMap<String, Option<Regex>>> = RefCell::new(HashMap::new());
+}
+
+/// Returns whether `value` matches `pattern`, or `None` if `pattern` isn't a valid
+/// regular expression.
+///
+/// An invalid pattern is a mistake in the tool definition, but no value is accepted
+/// for it, so that a typo doesn't turn off the check.
+fn matches_pattern(pattern: &str, value: &str) -> Option<bool> {
+    PATTERNS.with_borrow_mut(|patterns| {
+        if !patterns.contains_key(pattern) && patterns.len() >= MAX_CACHED_PATTERNS {
+            patterns.clear();
+        }
+        patterns
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern).ok())
+            .as_ref()
+            .map(|regex| regex.is_match(value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tool_call;
+    use crate::tool::{Bound, ParameterBuilder, ParameterType, ToolBuilder};
+
+    fn tools() -> Vec<Tool> {
+        vec![
+            ToolBuilder::new("convert")
+                .with_parameter(ParameterBuilder::new("value", ParameterType::Number).is_required())
+                .with_parameter(
+                    ParameterBuilder::new("unit", ParameterType::String)
+                        .with_enum_values(["meters", "feet"])
+                        .is_required(),
+                )
+                .with_parameter(ParameterBuilder::new("round", ParameterType::Boolean))
+                .build(),
+            ToolBuilder::new("search")
+                .with_parameter(ParameterBuilder::new("limit", ParameterType::Integer))
+                .with_parameter(ParameterBuilder::new(
+                    "accounts",
+                    ParameterType::array_of(ParameterType::String),
+                ))
+                .with_parameter(ParameterBuilder::new(
+                    "filter",
+                    ParameterType::Object(vec![]),
+                ))
+                .build(),
+            ToolBuilder::new("get_time").build(),
+            ToolBuilder::new("lookup_account")
+                .with_parameter(
+                    ParameterBuilder::new("account", ParameterType::String)
+                        .with_min_length(64)
+                        .with_max_length(64)
+                        .with_pattern("^[0-9a-f]*$")
+                        .is_required(),
+                )
+                .with_parameter(
+                    ParameterBuilder::new("decimals", ParameterType::Integer)
+                        .with_minimum(0.0)
+                        .with_maximum(8.0),
+                )
+                .with_parameter(ParameterBuilder::new("memo", ParameterType::String).is_nullable())
+                .build(),
+        ]
+    }
+
+    #[test]
+    fn accepts_valid_calls() {
+        let message = AssistantMessage {
+            content: None,
+            tool_calls: vec![
+                tool_call("call_1", "convert", &[("value", "2.5"), ("unit", "feet")]),
+                tool_call(
+                    "call_2",
+                    "convert",
+                    &[("value", "-3"), ("unit", "meters"), ("round", "true")],
+                ),
+                tool_call("call_3", "get_time", &[]),
+                tool_call(
+                    "call_4",
+                    "search",
+                    &[("limit", "10"), ("accounts", r#"["a"]"#), ("filter", "{}")],
+                ),
+            ],
+        };
+        assert_eq!(validate_tool_calls(&tools(), &message), Ok(()));
+    }
+
+    #[test]
+    fn reports_unknown_functions() {
+        assert_eq!(
+            validate_tool_call(&tools(), &tool_call("call_1", "get_date", &[])),
+            Err(InvalidToolCall {
+                tool_call_id: "call_1".to_string(),
+                function: "get_date".to_string(),
+                violations: vec![Violation::UnknownFunction],
+            })
+        );
+    }
+
+    #[test]
+    fn reports_all_violations_of_a_call() {
+        let error = validate_tool_call(
+            &tools(),
+            &tool_call(
+                "call_1",
+                "convert",
+                &[("value", "two"), ("round", "yes"), ("scale", "2")],
+            ),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.violations,
+            vec![
+                Violation::MissingArgument("unit".to_string()),
+                Violation::TypeMismatch {
+                    argument: "value".to_string(),
+                    value: "two".to_string(),
+                    expected: "number".to_string(),
+                },
+                Violation::TypeMismatch {
+                    argument: "round".to_string(),
+                    value: "yes".to_string(),
+                    expected: "boolean".to_string(),
+                },
+                Violation::UnexpectedArgument("scale".to_string()),
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid call to `convert`: missing required argument `unit`; \
+             argument `value` is \"two\", expected a number; \
+             argument `round` is \"yes\", expected a boolean; \
+             unexpected argument `scale`"
+        );
+    }
+
+    #[test]
+    fn checks_integers_arrays_and_objects() {
+        let error = validate_tool_call(
+            &tools(),
+            &tool_call(
+                "call_1",
+                "search",
+                &[("limit", "2.5"), ("accounts", "a"), ("filter", "[]")],
+            ),
+        )
+        .unwrap_err();
+
+        let expected: Vec<_> = [
+            ("limit", "2.5", "integer"),
+            ("accounts", "a", "array"),
+            ("filter", "[]", "object"),
+        ]
+        .into_iter()
+        .map(|(argument, value, expected)| Violation::TypeMismatch {
+            argument: argument.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        })
+        .collect();
+        assert_eq!(error.violations, expected);
+    }
+
+    #[test]
+    fn checks_the_elements_of_arrays() {
+        let tools = vec![ToolBuilder::new("transfer")
+            .with_parameter(
+                ParameterBuilder::new(
+                    "transfers",
+                    ParameterType::array_of(ParameterType::Object(vec![
+                        ParameterBuilder::new("account", ParameterType::String)
+                            .with_pattern("^[0-9a-f]{4}$")
+                            .is_required(),
+                        ParameterBuilder::new("amount", ParameterType::Integer),
+                    ])),
+                )
+                .is_required(),
+            )
+            .build()];
+
+        let valid = r#"[{"account": "00ff", "amount": 5}]"#;
+        assert_eq!(
+            validate_tool_call(
+                &tools,
+                &tool_call("call_1", "transfer", &[("transfers", valid)])
+            ),
+            Ok(())
+        );
+
+        let invalid = r#"[{"account": "00ff"}, {"account": "xyz", "amount": "5"}, {}]"#;
+        let error = validate_tool_call(
+            &tools,
+            &tool_call("call_1", "transfer", &[("transfers", invalid)]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.violations,
+            vec![Violation::InvalidNestedValue {
+                argument: "transfers".to_string(),
+                value: invalid.to_string(),
+                errors: vec![
+                    "`/1/account` must be a match for `^[0-9a-f]{4}$`".to_string(),
+                    "`/1/amount` must be of type integer".to_string(),
+                    "missing required field `/2/account`".to_string(),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn enforces_constraints() {
+        let account = "a".repeat(64);
+        let check = |arguments: &[(&str, &str)]| {
+            validate_tool_call(&tools(), &tool_call("call_1", "lookup_account", arguments))
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            check(&[("account", &account), ("decimals", "8"), ("memo", "null")]),
+            Ok(())
+        );
+        assert_eq!(
+            check(&[("account", "abc")]),
+            Err(
+                "invalid call to `lookup_account`: argument `account` is \"abc\", \
+                 expected at least 64 characters"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(&[("account", &"g".repeat(64))]),
+            Err(format!(
+                "invalid call to `lookup_account`: argument `account` is \"{}\", \
+                 expected a match for `^[0-9a-f]*$`",
+                "g".repeat(64)
+            ))
+        );
+        assert_eq!(
+            check(&[("account", &account), ("decimals", "-1")]),
+            Err(
+                "invalid call to `lookup_account`: argument `decimals` is \"-1\", \
+                 expected at least 0"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(&[("account", &account), ("decimals", "9")]),
+            Err(
+                "invalid call to `lookup_account`: argument `decimals` is \"9\", \
+                 expected at most 8"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn applies_constraints_for_the_declared_type_only() {
+        let code = Property {
+            type_: "string".to_string(),
+            name: "code".to_string(),
+            minimum: Some(Bound(0.0)),
+            ..Default::default()
+        };
+        assert_eq!(unmet_constraint(&code, "-1"), None);
+
+        let count = Property {
+            type_: "integer".to_string(),
+            name: "count".to_string(),
+            max_length: Some(1),
+            pattern: Some("^[0-9]$".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(unmet_constraint(&count, "100"), None);
+    }
+
+    #[test]
+    fn rejects_every_value_for_invalid_patterns() {
+        let account = Property {
+            type_: "string".to_string(),
+            name: "account".to_string(),
+            pattern: Some("^[0-9a-f*$".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            unmet_constraint(&account, "00ff"),
+            Some("a match for `^[0-9a-f*$`, which isn't a valid regular expression".to_string())
+        );
+    }
+
+    #[test]
+    fn bounds_the_pattern_cache() {
+        for i in 0..2 * MAX_CACHED_PATTERNS {
+            assert_eq!(
+                matches_pattern(&format!("^{i}$"), &i.to_string()),
+                Some(true)
+            );
+        }
+        assert!(PATTERNS.with_borrow(HashMap::len) <= MAX_CACHED_PATTERNS);
+    }
+
+    #[test]
+    fn reports_enum_violations_per_call() {
+        let message = AssistantMessage {
+            content: None,
+            tool_calls: vec![
+                tool_call("call_1", "convert", &[("value", "1"), ("unit", "miles")]),
+                tool_call("call_2", "get_time", &[]),
+            ],
+        };
+
+        let invalid = validate_tool_calls(&tools(), &message).unwrap_err();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].tool_call_id, "call_1");
+        assert_eq!(
+            invalid[0].violations,
+            vec![Violation::NotInEnum {
+                argument: "unit".to_string(),
+                value: "miles".to_string(),
+                allowed: vec!["meters".to_string(), "feet".to_string()],
+            }]
+        );
+    }
+}